@@ -4,11 +4,21 @@ use seed::*;
 use crate::msg::Msg;
 use crate::scale::scale;
 
+// Length of a tick mark and the gap before its label.
+const TICK: f64 = 5.;
+const GAP: f64 = 3.;
+
 pub struct Axis {
   domain: (f64, f64),
   range: (f64, f64),
   dir: Direction,
-  min_unit: f64,
+  interval: f64,
+  format: fn(f64) -> String,
+  // gridlines run this far across the chart, `None` for no grid
+  grid: Option<f64>,
+  title: Option<String>,
+  // vertical axes mark to the left unless set
+  right: bool,
 }
 
 pub enum Direction {
@@ -16,74 +26,181 @@ pub enum Direction {
   Vertical,
 }
 
+// Multiples of `interval` within `domain`, ends included.
+pub fn ticks(domain: (f64, f64), interval: f64) -> Vec<f64> {
+  if interval <= 0. {
+    return vec![];
+  }
+  // slack for domains that are a float sum of intervals
+  let slack = interval * 1e-9;
+  let first = ((domain.0 - slack) / interval).ceil() as i64;
+  let last = ((domain.1 + slack) / interval).floor() as i64;
+  (first..=last).map(|i| i as f64 * interval).collect()
+}
+
 impl Axis {
-  pub fn new(domain: (f64, f64), range: (f64, f64), dir: Direction, min_unit: f64) -> Self {
+  pub fn new(domain: (f64, f64), range: (f64, f64), dir: Direction, interval: f64) -> Self {
     Self {
       domain,
       range,
       dir,
-      min_unit,
+      interval,
+      format: |v| format!("{}", v),
+      grid: None,
+      title: None,
+      right: false,
     }
   }
 
-  pub fn render(&self) -> Node<Msg> {
-    match self.dir {
-      Direction::Horizontal => {
-        g![
-          line_![attrs![
-            At::X1 => 0.,
-            At::Y1 => 0.,
-            At::X2 => self.range.1,
-            At::Y2 => 0.,
-            At::Stroke => "darkgray",
-            At::StrokeWidth => "1.25px",
-            At::StrokeLinecap => "round",
-          ]],
-          self.render_marks(),
-        ]
-      }
-      Direction::Vertical => {
-        g![
-          line_![attrs![
-            At::X1 => 0.,
-            At::Y1 => 0.,
-            At::X2 => 0.,
-            At::Y2 => self.range.1,
-            At::Stroke => "darkgray",
-            At::StrokeWidth => "1.25px",
-            At::StrokeLinecap => "round",
-          ]],
-          g![],
-        ]
-      }
+  pub fn format(self, format: fn(f64) -> String) -> Self {
+    Self { format, ..self }
+  }
+
+  pub fn grid(self, length: f64) -> Self {
+    Self {
+      grid: Some(length),
+      ..self
     }
   }
 
-  fn render_marks(&self) -> Node<Msg> {
-    match self.dir {
-      Direction::Horizontal => {
-        let x = scale(self.domain, self.range);
-        let mut t = self.min_unit;
-        let mut list = vec![];
-        loop {
-          list.push(line_![attrs![
-            At::X1 => x(t),
-            At::Y1 => 0.,
-            At::X2 => x(t),
-            At::Y2 => 10.,
-            At::Stroke => "darkgray",
-            At::StrokeWidth => "0.75px",
-            At::StrokeLinecap => "round",
-          ]]);
-
-          t += self.min_unit;
-          if t > self.domain.1 {
-            break;
-          }
-        }
-        g![list]
-      }
-      _ => g![],
+  pub fn title(self, title: &str) -> Self {
+    Self {
+      title: Some(title.into()),
+      ..self
     }
   }
+
+  pub fn right(self) -> Self {
+    Self {
+      right: true,
+      ..self
+    }
+  }
+
+  pub fn render(&self) -> Node<Msg> {
+    let (x2, y2) = match self.dir {
+      Direction::Horizontal => (self.range.1, 0.),
+      Direction::Vertical => (0., self.range.1),
+    };
+    g![
+      self.render_grid(),
+      line_![attrs![
+        At::X1 => 0.,
+        At::Y1 => 0.,
+        At::X2 => x2,
+        At::Y2 => y2,
+        At::Stroke => "darkgray",
+        At::StrokeWidth => "1.25px",
+        At::StrokeLinecap => "round",
+      ]],
+      self.render_marks(),
+      self.render_title(),
+    ]
+  }
+
+  // Tick positions along the axis with their labels.
+  fn marks(&self) -> Vec<(f64, String)> {
+    let at = scale(self.domain, self.range);
+    ticks(self.domain, self.interval)
+      .into_iter()
+      .map(|v| (at(v), (self.format)(v)))
+      .collect()
+  }
+
+  fn render_grid(&self) -> Node<Msg> {
+    let length = match self.grid {
+      Some(length) => length,
+      None => return empty![],
+    };
+    g![self.marks().into_iter().map(|(p, _)| {
+      let (x1, y1, x2, y2) = match self.dir {
+        Direction::Horizontal => (p, 0., p, length),
+        Direction::Vertical => (0., p, length, p),
+      };
+      line_![attrs![
+        At::X1 => x1,
+        At::Y1 => y1,
+        At::X2 => x2,
+        At::Y2 => y2,
+        At::Stroke => "gainsboro",
+        At::StrokeWidth => "0.5px",
+      ]]
+    })]
+  }
+
+  fn render_marks(&self) -> Node<Msg> {
+    let side = if self.right { 1. } else { -1. };
+    g![self.marks().into_iter().map(|(p, label)| {
+      let (tick, (x, y), anchor, baseline) = match self.dir {
+        Direction::Horizontal => ((p, 0., p, TICK), (p, TICK + GAP), "middle", "hanging"),
+        Direction::Vertical => (
+          (0., p, side * TICK, p),
+          (side * (TICK + GAP), p),
+          if self.right { "start" } else { "end" },
+          "middle",
+        ),
+      };
+      let (x1, y1, x2, y2) = tick;
+      g![
+        line_![attrs![
+          At::X1 => x1,
+          At::Y1 => y1,
+          At::X2 => x2,
+          At::Y2 => y2,
+          At::Stroke => "darkgray",
+          At::StrokeWidth => "0.75px",
+          At::StrokeLinecap => "round",
+        ]],
+        text![
+          attrs![
+            At::X => x,
+            At::Y => y,
+            At::FontSize => "9px",
+            At::Fill => "dimgray",
+            At::TextAnchor => anchor,
+            At::DominantBaseline => baseline,
+          ],
+          label,
+        ],
+      ]
+    })]
+  }
+
+  // Horizontal titles sit at the far end below the labels, vertical ones
+  // above the top of the axis.
+  fn render_title(&self) -> Node<Msg> {
+    let title = match &self.title {
+      Some(title) => title,
+      None => return empty![],
+    };
+    let (x, y, anchor) = match self.dir {
+      Direction::Horizontal => (self.range.1, TICK + GAP + 20., "end"),
+      Direction::Vertical => (0., self.range.1 - 6., "middle"),
+    };
+    text![
+      attrs![
+        At::X => x,
+        At::Y => y,
+        At::FontSize => "10px",
+        At::Fill => "dimgray",
+        At::TextAnchor => anchor,
+      ],
+      title,
+    ]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_ticks() {
+    assert_eq!(ticks((0., 35.), 10.), vec![0., 10., 20., 30.]);
+    assert_eq!(ticks((0., 12.), 1.).len(), 13);
+    assert_eq!(ticks((5., 20.), 10.), vec![10., 20.]);
+    assert_eq!(ticks((0., 0.3), 0.1).len(), 4);
+    assert_eq!(ticks((0., 0.), 10.), vec![0.]);
+    assert_eq!(ticks((0., 10.), 0.), Vec::<f64>::new());
+  }
 }
@@ -0,0 +1,184 @@
+use crate::classify::{classify, Style};
+use crate::parser::PumpType;
+use crate::profile::Preset;
+
+// What the preset browser narrows the list down to. Every set bound must
+// hold; an empty query matches everything.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+  pub query: String,
+  pub beverage_type: Option<String>,
+  pub pump: Option<PumpType>,
+  pub style: Option<Style>,
+  pub min_steps: Option<usize>,
+  pub max_steps: Option<usize>,
+  pub min_seconds: Option<f64>,
+  pub max_seconds: Option<f64>,
+}
+
+pub fn duration(preset: &Preset) -> f64 {
+  preset.steps.iter().map(|step| step.seconds).sum()
+}
+
+fn within<T: PartialOrd>(v: T, min: Option<T>, max: Option<T>) -> bool {
+  min.is_none_or(|min| min <= v) && max.is_none_or(|max| v <= max)
+}
+
+impl Filter {
+  // Each word of the query has to appear in the title, author or notes.
+  fn matches_query(&self, preset: &Preset) -> bool {
+    let text = format!(
+      "{}\n{}\n{}",
+      preset.title,
+      preset.document.author.as_deref().unwrap_or_default(),
+      preset.notes
+    )
+    .to_lowercase();
+    self
+      .query
+      .to_lowercase()
+      .split_whitespace()
+      .all(|word| text.contains(word))
+  }
+
+  pub fn matches(&self, preset: &Preset) -> bool {
+    let beverage_type = preset.document.beverage_type.as_deref();
+    self.matches_query(preset)
+      && self
+        .beverage_type
+        .as_deref()
+        .is_none_or(|v| beverage_type == Some(v))
+      && self
+        .pump
+        .is_none_or(|pump| preset.steps.iter().any(|step| step.pump == pump))
+      && self
+        .style
+        .is_none_or(|style| classify(&preset.steps).contains(&style))
+      && within(preset.steps.len(), self.min_steps, self.max_steps)
+      && within(duration(preset), self.min_seconds, self.max_seconds)
+  }
+}
+
+// Matching presets sorted by title, with their keys.
+pub fn search<'a>(
+  presets: impl Iterator<Item = (&'a String, &'a Preset)>,
+  filter: &Filter,
+) -> Vec<(&'a String, &'a Preset)> {
+  let mut list = presets
+    .filter(|(_, preset)| filter.matches(preset))
+    .collect::<Vec<_>>();
+  list.sort_by(|(k1, p1), (k2, p2)| p1.title.cmp(&p2.title).then(k1.cmp(k2)));
+  list
+}
+
+pub fn beverage_types<'a>(presets: impl Iterator<Item = &'a Preset>) -> Vec<String> {
+  let mut list = presets
+    .filter_map(|preset| preset.document.beverage_type.clone())
+    .collect::<Vec<_>>();
+  list.sort();
+  list.dedup();
+  list
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::profile::Source;
+
+  fn preset(text: &str) -> Preset {
+    Preset::load(text, Source::User).unwrap()
+  }
+
+  fn titles(presets: &[Preset], filter: &Filter) -> Vec<String> {
+    let keys = presets
+      .iter()
+      .map(|preset| preset.title.clone())
+      .collect::<Vec<_>>();
+    search(keys.iter().zip(presets.iter()), filter)
+      .iter()
+      .map(|(key, _)| key.to_string())
+      .collect()
+  }
+
+  fn presets() -> Vec<Preset> {
+    vec![
+      preset(
+        "profile_title Blooming\nauthor Decent\nbeverage_type espresso\n\
+         profile_notes {Long bloom at low pressure.}\n\
+         advanced_shot {{pump flow flow 4 seconds 20} {pump pressure pressure 9 seconds 30}}",
+      ),
+      preset(
+        "profile_title {Tea portafilter}\nbeverage_type tea_portafilter\n\
+         advanced_shot {{pump flow flow 2 seconds 60}}",
+      ),
+      preset(
+        "profile_title Cleaning\nbeverage_type cleaning\n\
+         advanced_shot {{pump pressure pressure 3 seconds 5}}",
+      ),
+    ]
+  }
+
+  #[test]
+  fn test_query() {
+    let presets = presets();
+    let filter = |query: &str| Filter {
+      query: query.into(),
+      ..Filter::default()
+    };
+    assert_eq!(
+      titles(&presets, &filter("")),
+      vec!["Blooming", "Cleaning", "Tea portafilter"]
+    );
+    assert_eq!(titles(&presets, &filter("decent")), vec!["Blooming"]);
+    assert_eq!(titles(&presets, &filter("LOW bloom")), vec!["Blooming"]);
+    assert_eq!(titles(&presets, &filter("bloom tea")), Vec::<String>::new());
+  }
+
+  #[test]
+  fn test_filters() {
+    let presets = presets();
+    let only_flow = Filter {
+      pump: Some(PumpType::Flow),
+      ..Filter::default()
+    };
+    assert_eq!(
+      titles(&presets, &only_flow),
+      vec!["Blooming", "Tea portafilter"]
+    );
+
+    let espresso = Filter {
+      beverage_type: Some("espresso".into()),
+      ..Filter::default()
+    };
+    assert_eq!(titles(&presets, &espresso), vec!["Blooming"]);
+
+    let short = Filter {
+      max_steps: Some(1),
+      max_seconds: Some(30.),
+      ..Filter::default()
+    };
+    assert_eq!(titles(&presets, &short), vec!["Cleaning"]);
+
+    let long = Filter {
+      min_seconds: Some(50.),
+      min_steps: Some(2),
+      ..Filter::default()
+    };
+    assert_eq!(titles(&presets, &long), vec!["Blooming"]);
+
+    let flow_profiled = Filter {
+      style: Some(Style::FlowProfiled),
+      ..Filter::default()
+    };
+    assert_eq!(titles(&presets, &flow_profiled), vec!["Tea portafilter"]);
+  }
+
+  #[test]
+  fn test_beverage_types() {
+    let presets = presets();
+    assert_eq!(
+      beverage_types(presets.iter()),
+      vec!["cleaning", "espresso", "tea_portafilter"]
+    );
+  }
+}
@@ -0,0 +1,311 @@
+use std::fmt;
+
+use crate::parser::{ExitType, PumpType};
+use crate::step::AdvancedStep;
+
+// A lever peaks at least this high, in bar...
+const LEVER_PEAK: f64 = 6.;
+// ...and declines by at least this much.
+const LEVER_DROP: f64 = 2.;
+// Below this a pressure or flow target counts as a pause.
+const BLOOM_TARGET: f64 = 0.5;
+const BLOOM_SECONDS: f64 = 5.;
+// The most pressure a turbo shot lets build, in bar.
+const TURBO_PRESSURE: f64 = 6.;
+// Flow that keeps running for an allongé, in ml/s.
+const ALLONGE_FLOW: f64 = 3.5;
+const ALLONGE_SECONDS: f64 = 20.;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+  Lever,
+  Blooming,
+  Turbo,
+  Allonge,
+  FlowProfiled,
+  Adaptive,
+}
+
+impl Style {
+  pub const ALL: [Style; 6] = [
+    Style::Lever,
+    Style::Blooming,
+    Style::Turbo,
+    Style::Allonge,
+    Style::FlowProfiled,
+    Style::Adaptive,
+  ];
+
+  pub fn parse(value: &str) -> Option<Self> {
+    Style::ALL
+      .iter()
+      .copied()
+      .find(|style| style.as_str() == value)
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Style::Lever => "lever",
+      Style::Blooming => "blooming",
+      Style::Turbo => "turbo",
+      Style::Allonge => "allonge",
+      Style::FlowProfiled => "flow",
+      Style::Adaptive => "adaptive",
+    }
+  }
+}
+
+impl fmt::Display for Style {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let label = match self {
+      Style::Lever => "lever",
+      Style::Blooming => "blooming",
+      Style::Turbo => "turbo",
+      Style::Allonge => "allongé",
+      Style::FlowProfiled => "flow profiled",
+      Style::Adaptive => "adaptive",
+    };
+    write!(f, "{}", label)
+  }
+}
+
+fn pressure_targets(steps: &[AdvancedStep]) -> Vec<f64> {
+  steps
+    .iter()
+    .filter(|step| step.pump == PumpType::Pressure)
+    .map(|step| step.pressure)
+    .collect()
+}
+
+// Pressure peaks at `LEVER_PEAK` or more, then only goes down, ending
+// `LEVER_DROP` or more below the peak.
+fn is_lever(steps: &[AdvancedStep]) -> bool {
+  let targets = pressure_targets(steps);
+  let peak = match targets
+    .iter()
+    .enumerate()
+    .fold(None, |peak: Option<(usize, f64)>, (i, &v)| match peak {
+      Some((_, p)) if p >= v => peak,
+      _ => Some((i, v)),
+    }) {
+    Some((i, v)) if v >= LEVER_PEAK => i,
+    _ => return false,
+  };
+  let decline = &targets[peak..];
+  decline.windows(2).all(|pair| pair[1] <= pair[0])
+    && decline
+      .last()
+      .is_some_and(|&v| v <= targets[peak] - LEVER_DROP)
+}
+
+// After water first flows, a step pauses the pump for `BLOOM_SECONDS` or
+// more.
+fn is_blooming(steps: &[AdvancedStep]) -> bool {
+  steps
+    .iter()
+    .skip_while(|step| step.target() <= BLOOM_TARGET)
+    .any(|step| step.target() <= BLOOM_TARGET && step.seconds >= BLOOM_SECONDS)
+}
+
+// Every step that pumps caps pressure at `TURBO_PRESSURE`: with its target,
+// a pressure limiter or an exit on rising pressure.
+fn is_turbo(steps: &[AdvancedStep]) -> bool {
+  let ceiling = |step: &AdvancedStep| match step.pump {
+    PumpType::Pressure => Some(step.pressure),
+    PumpType::Flow => {
+      let limiter = Some(step.max_flow_or_pressure).filter(|v| *v > 0.);
+      let exit = match step.exit() {
+        Some((ExitType::PressureOver, v)) => Some(v),
+        _ => None,
+      };
+      match (limiter, exit) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+      }
+    }
+  };
+  let pumping = steps
+    .iter()
+    .filter(|step| step.target() > 0.)
+    .collect::<Vec<_>>();
+  !pumping.is_empty()
+    && pumping
+      .iter()
+      .all(|step| ceiling(step).is_some_and(|v| v <= TURBO_PRESSURE))
+}
+
+// Flow steps of `ALLONGE_FLOW` or more that run their full time add up to
+// `ALLONGE_SECONDS` or more.
+fn is_allonge(steps: &[AdvancedStep]) -> bool {
+  let seconds: f64 = steps
+    .iter()
+    .filter(|step| step.pump == PumpType::Flow && step.flow >= ALLONGE_FLOW)
+    .filter(|step| step.exit().is_none())
+    .map(|step| step.seconds)
+    .sum();
+  seconds >= ALLONGE_SECONDS
+}
+
+// Flow steps that pump take at least half the time after the first step,
+// which is usually the fill.
+fn is_flow_profiled(steps: &[AdvancedStep]) -> bool {
+  let extraction = if steps.len() > 1 { &steps[1..] } else { steps };
+  let total: f64 = extraction.iter().map(|step| step.seconds).sum();
+  let flow: f64 = extraction
+    .iter()
+    .filter(|step| step.pump == PumpType::Flow && step.flow > 0.)
+    .map(|step| step.seconds)
+    .sum();
+  flow > 0. && flow * 2. >= total
+}
+
+// Any step leaves it to a limiter to adapt to the puck.
+fn is_adaptive(steps: &[AdvancedStep]) -> bool {
+  steps.iter().any(|step| step.max_flow_or_pressure > 0.)
+}
+
+// The styles the step list matches, in `Style::ALL` order.
+pub fn classify(steps: &[AdvancedStep]) -> Vec<Style> {
+  Style::ALL
+    .iter()
+    .copied()
+    .filter(|style| {
+      let rule = match style {
+        Style::Lever => is_lever,
+        Style::Blooming => is_blooming,
+        Style::Turbo => is_turbo,
+        Style::Allonge => is_allonge,
+        Style::FlowProfiled => is_flow_profiled,
+        Style::Adaptive => is_adaptive,
+      };
+      rule(steps)
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn pressure(v: f64, seconds: f64) -> AdvancedStep {
+    AdvancedStep {
+      pressure: v,
+      seconds,
+      ..AdvancedStep::default()
+    }
+  }
+
+  fn flow(v: f64, seconds: f64) -> AdvancedStep {
+    AdvancedStep {
+      pump: PumpType::Flow,
+      flow: v,
+      seconds,
+      ..AdvancedStep::default()
+    }
+  }
+
+  fn fill() -> AdvancedStep {
+    AdvancedStep {
+      exit_if: true,
+      exit_type: Some(ExitType::PressureOver),
+      exit_pressure_over: 4.,
+      ..flow(6., 20.)
+    }
+  }
+
+  #[test]
+  fn test_parse() {
+    for style in Style::ALL.iter() {
+      assert_eq!(Style::parse(style.as_str()), Some(*style));
+    }
+    assert_eq!(Style::parse("ristretto"), None);
+    assert_eq!(Style::Allonge.to_string(), "allongé");
+  }
+
+  #[test]
+  fn test_lever() {
+    let lever = [
+      fill(),
+      pressure(9., 4.),
+      pressure(9., 10.),
+      pressure(6., 30.),
+    ];
+    assert_eq!(classify(&lever), vec![Style::Lever]);
+    // rising again after the peak
+    assert!(!is_lever(&[
+      pressure(9., 10.),
+      pressure(6., 10.),
+      pressure(8., 10.)
+    ]));
+    // too gentle a decline
+    assert!(!is_lever(&[pressure(9., 10.), pressure(8., 10.)]));
+    // never high enough
+    assert!(!is_lever(&[pressure(5., 10.), pressure(2., 10.)]));
+  }
+
+  #[test]
+  fn test_blooming() {
+    let bloom = [fill(), flow(0., 30.), pressure(9., 30.)];
+    assert_eq!(classify(&bloom), vec![Style::Blooming]);
+    // a pause before any water doesn't bloom the puck
+    assert!(!is_blooming(&[pressure(0., 10.), pressure(9., 30.)]));
+    assert!(!is_blooming(&[fill(), flow(0., 3.), pressure(9., 30.)]));
+  }
+
+  #[test]
+  fn test_turbo() {
+    let turbo = AdvancedStep {
+      max_flow_or_pressure: 6.,
+      ..flow(4.5, 15.)
+    };
+    assert_eq!(
+      classify(&[fill(), turbo.clone()]),
+      vec![Style::Turbo, Style::FlowProfiled, Style::Adaptive]
+    );
+    assert!(is_turbo(&[fill(), pressure(6., 20.)]));
+    // an unlimited flow step can build any pressure
+    assert!(!is_turbo(&[turbo, flow(2., 10.)]));
+    assert!(!is_turbo(&[]));
+  }
+
+  #[test]
+  fn test_allonge() {
+    let allonge = AdvancedStep {
+      max_flow_or_pressure: 4.,
+      ..flow(4.5, 40.)
+    };
+    assert_eq!(
+      classify(&[fill(), allonge]),
+      vec![
+        Style::Turbo,
+        Style::Allonge,
+        Style::FlowProfiled,
+        Style::Adaptive
+      ]
+    );
+    // the fill exits early, so its seconds don't count
+    assert!(!is_allonge(&[fill(), flow(4., 10.)]));
+  }
+
+  #[test]
+  fn test_flow_profiled() {
+    assert!(is_flow_profiled(&[flow(2., 60.)]));
+    assert!(is_flow_profiled(&[
+      pressure(9., 30.),
+      flow(2., 20.),
+      pressure(6., 20.)
+    ]));
+    assert!(!is_flow_profiled(&[fill(), pressure(9., 30.)]));
+    assert!(!is_flow_profiled(&[]));
+  }
+
+  #[test]
+  fn test_adaptive() {
+    let limited = AdvancedStep {
+      max_flow_or_pressure: 2.5,
+      ..pressure(9., 30.)
+    };
+    assert_eq!(classify(&[fill(), limited]), vec![Style::Adaptive]);
+    assert!(!is_adaptive(&[fill(), pressure(9., 30.)]));
+  }
+}
@@ -0,0 +1,360 @@
+use crate::document::SimpleSettings;
+use crate::parser::{ExitType, Prop, PumpType, SensorType, Step, TransitionType};
+
+// How long the app boosts preinfusion to `espresso_temperature_0` when
+// temperature steps are enabled.
+const TEMPERATURE_BOOST_SECONDS: f64 = 2.;
+// How long the app rises without the flow limiter before a limited hold.
+const FORCED_RISE_SECONDS: f64 = 3.;
+
+// Per-stage temperatures: with temperature steps enabled the app uses
+// `espresso_temperature_0` to `_3` for boost, preinfusion, hold and decline,
+// otherwise `espresso_temperature` throughout.
+fn temperatures(settings: &SimpleSettings, steps_enabled: bool) -> [f64; 4] {
+  let base = settings.espresso_temperature.unwrap_or_default();
+  if !steps_enabled {
+    return [base; 4];
+  }
+  [
+    settings.espresso_temperature_0.unwrap_or(base),
+    settings.espresso_temperature_1.unwrap_or(base),
+    settings.espresso_temperature_2.unwrap_or(base),
+    settings.espresso_temperature_3.unwrap_or(base),
+  ]
+}
+
+fn step(name: &str, temperature: f64, pump: PumpType, transition: TransitionType) -> Vec<Prop> {
+  vec![
+    Prop::Name(name.into()),
+    Prop::Temperature(temperature),
+    Prop::Sensor(SensorType::Coffee),
+    Prop::Pump(pump),
+    Prop::Transition(transition),
+  ]
+}
+
+fn no_exit() -> Vec<Prop> {
+  vec![Prop::ExitIf(false)]
+}
+
+fn exit_pressure_over(v: f64) -> Vec<Prop> {
+  vec![
+    Prop::ExitIf(true),
+    Prop::ExitType(ExitType::PressureOver),
+    Prop::ExitPressureOver(v),
+  ]
+}
+
+fn limiter(v: Option<f64>, range: Option<f64>) -> Vec<Prop> {
+  match v {
+    Some(v) if v > 0. => vec![
+      Prop::MaxFlowOrPressure(v),
+      Prop::MaxFlowOrPressureRange(range.unwrap_or(0.6)),
+    ],
+    _ => vec![],
+  }
+}
+
+// Preinfusion fills at a fixed flow until pressure builds, split in two when
+// temperature steps boost the start of it.
+fn preinfusion(
+  flow: f64,
+  seconds: f64,
+  stop_pressure: f64,
+  temperatures: [f64; 4],
+  boost: bool,
+) -> Vec<Step> {
+  let mut list = vec![];
+  let mut seconds = seconds;
+  if boost && seconds > 0. {
+    let boost_seconds = seconds.min(TEMPERATURE_BOOST_SECONDS);
+    let mut props = step(
+      "preinfusion temp boost",
+      temperatures[0],
+      PumpType::Flow,
+      TransitionType::Fast,
+    );
+    props.extend(vec![
+      Prop::Flow(flow),
+      Prop::Pressure(1.),
+      Prop::Seconds(boost_seconds),
+    ]);
+    props.extend(exit_pressure_over(stop_pressure));
+    list.push(Step(props));
+    seconds -= boost_seconds;
+  }
+
+  if seconds > 0. {
+    let mut props = step(
+      "preinfusion",
+      temperatures[1],
+      PumpType::Flow,
+      TransitionType::Fast,
+    );
+    props.extend(vec![
+      Prop::Flow(flow),
+      Prop::Pressure(1.),
+      Prop::Seconds(seconds),
+    ]);
+    props.extend(exit_pressure_over(stop_pressure));
+    list.push(Step(props));
+  }
+  list
+}
+
+// The steps the DE1 app generates for a simple pressure profile
+// (`settings_2a`): preinfusion, rise and hold, then a smooth decline.
+pub fn pressure_steps(settings: &SimpleSettings, temperature_steps: bool) -> Vec<Step> {
+  let temperatures = temperatures(settings, temperature_steps);
+  let mut list = preinfusion(
+    settings.preinfusion_flow_rate.unwrap_or_default(),
+    settings.preinfusion_time.unwrap_or_default(),
+    settings.preinfusion_stop_pressure.unwrap_or_default(),
+    temperatures,
+    temperature_steps,
+  );
+
+  let pressure = settings.espresso_pressure.unwrap_or_default();
+  let limit = limiter(settings.maximum_flow, settings.maximum_flow_range_default);
+  let mut hold_seconds = settings.espresso_hold_time.unwrap_or_default();
+  if !limit.is_empty() && hold_seconds > 0. {
+    let rise_seconds = hold_seconds.min(FORCED_RISE_SECONDS);
+    let mut props = step(
+      "forced rise without limit",
+      temperatures[2],
+      PumpType::Pressure,
+      TransitionType::Fast,
+    );
+    props.extend(vec![Prop::Pressure(pressure), Prop::Seconds(rise_seconds)]);
+    props.extend(no_exit());
+    list.push(Step(props));
+    hold_seconds -= rise_seconds;
+  }
+
+  if hold_seconds > 0. {
+    let mut props = step(
+      "rise and hold",
+      temperatures[2],
+      PumpType::Pressure,
+      TransitionType::Fast,
+    );
+    props.extend(vec![Prop::Pressure(pressure), Prop::Seconds(hold_seconds)]);
+    props.extend(no_exit());
+    props.extend(limit.clone());
+    list.push(Step(props));
+  }
+
+  let decline_seconds = settings.espresso_decline_time.unwrap_or_default();
+  if decline_seconds > 0. {
+    let mut props = step(
+      "decline",
+      temperatures[3],
+      PumpType::Pressure,
+      TransitionType::Smooth,
+    );
+    props.extend(vec![
+      Prop::Pressure(settings.pressure_end.unwrap_or_default()),
+      Prop::Seconds(decline_seconds),
+    ]);
+    props.extend(no_exit());
+    props.extend(limit);
+    list.push(Step(props));
+  }
+  list
+}
+
+// The steps the DE1 app generates for a simple flow profile (`settings_2b`):
+// preinfusion, hold and a smooth decline, with `maximum_pressure` limiting
+// the flow steps.
+pub fn flow_steps(settings: &SimpleSettings, temperature_steps: bool) -> Vec<Step> {
+  let temperatures = temperatures(settings, temperature_steps);
+  let mut list = preinfusion(
+    settings.flow_profile_preinfusion.unwrap_or_default(),
+    settings.flow_profile_preinfusion_time.unwrap_or_default(),
+    settings.preinfusion_stop_pressure.unwrap_or_default(),
+    temperatures,
+    temperature_steps,
+  );
+
+  let limit = limiter(
+    settings.maximum_pressure,
+    settings.maximum_pressure_range_default,
+  );
+  let hold_seconds = settings.flow_profile_hold_time.unwrap_or_default();
+  if hold_seconds > 0. {
+    let mut props = step(
+      "hold",
+      temperatures[2],
+      PumpType::Flow,
+      TransitionType::Fast,
+    );
+    props.extend(vec![
+      Prop::Flow(settings.flow_profile_hold.unwrap_or_default()),
+      Prop::Seconds(hold_seconds),
+    ]);
+    props.extend(no_exit());
+    props.extend(limit.clone());
+    list.push(Step(props));
+  }
+
+  let decline_seconds = settings.flow_profile_decline_time.unwrap_or_default();
+  if decline_seconds > 0. {
+    let mut props = step(
+      "decline",
+      temperatures[3],
+      PumpType::Flow,
+      TransitionType::Smooth,
+    );
+    props.extend(vec![
+      Prop::Flow(settings.flow_profile_decline.unwrap_or_default()),
+      Prop::Seconds(decline_seconds),
+    ]);
+    props.extend(no_exit());
+    props.extend(limit);
+    list.push(Step(props));
+  }
+  list
+}
+
+#[cfg(test)]
+mod tests {
+  use std::convert::TryFrom;
+
+  use super::*;
+  use crate::document::ProfileDocument;
+  use crate::profile::fixture_profiles;
+  use crate::step::AdvancedStep;
+
+  fn names(list: &[Step]) -> Vec<(String, f64)> {
+    list
+      .iter()
+      .map(|step| {
+        let step = AdvancedStep::try_from(step).unwrap();
+        (step.name, step.seconds)
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_pressure_steps() {
+    let settings = SimpleSettings {
+      espresso_temperature: Some(90.),
+      preinfusion_time: Some(20.),
+      preinfusion_flow_rate: Some(4.),
+      preinfusion_stop_pressure: Some(4.),
+      espresso_pressure: Some(8.6),
+      espresso_hold_time: Some(10.),
+      espresso_decline_time: Some(30.),
+      pressure_end: Some(6.),
+      ..SimpleSettings::default()
+    };
+    let list = pressure_steps(&settings, false);
+    assert_eq!(
+      names(&list),
+      vec![
+        ("preinfusion".into(), 20.),
+        ("rise and hold".into(), 10.),
+        ("decline".into(), 30.),
+      ]
+    );
+
+    let preinfusion = AdvancedStep::try_from(&list[0]).unwrap();
+    assert_eq!(preinfusion.pump, PumpType::Flow);
+    assert_eq!(preinfusion.flow, 4.);
+    assert_eq!(preinfusion.exit(), Some((ExitType::PressureOver, 4.)));
+    let decline = AdvancedStep::try_from(&list[2]).unwrap();
+    assert_eq!(decline.transition, TransitionType::Smooth);
+    assert_eq!(decline.pressure, 6.);
+    assert_eq!(decline.temperature, 90.);
+  }
+
+  #[test]
+  fn test_pressure_steps_limited() {
+    let settings = SimpleSettings {
+      espresso_pressure: Some(9.),
+      espresso_hold_time: Some(10.),
+      maximum_flow: Some(2.5),
+      ..SimpleSettings::default()
+    };
+    let list = pressure_steps(&settings, false);
+    assert_eq!(
+      names(&list),
+      vec![
+        ("forced rise without limit".into(), 3.),
+        ("rise and hold".into(), 7.),
+      ]
+    );
+    let hold = AdvancedStep::try_from(&list[1]).unwrap();
+    assert_eq!(hold.max_flow_or_pressure, 2.5);
+    assert_eq!(hold.max_flow_or_pressure_range, 0.6);
+  }
+
+  #[test]
+  fn test_flow_steps() {
+    let settings = SimpleSettings {
+      espresso_temperature: Some(93.),
+      espresso_temperature_0: Some(95.),
+      espresso_temperature_1: Some(93.),
+      espresso_temperature_2: Some(92.),
+      espresso_temperature_3: Some(91.),
+      flow_profile_preinfusion: Some(4.),
+      flow_profile_preinfusion_time: Some(20.),
+      flow_profile_hold: Some(2.2),
+      flow_profile_hold_time: Some(8.),
+      flow_profile_decline: Some(1.5),
+      flow_profile_decline_time: Some(20.),
+      preinfusion_stop_pressure: Some(4.),
+      maximum_pressure: Some(9.),
+      ..SimpleSettings::default()
+    };
+    let list = flow_steps(&settings, true);
+    assert_eq!(
+      names(&list),
+      vec![
+        ("preinfusion temp boost".into(), 2.),
+        ("preinfusion".into(), 18.),
+        ("hold".into(), 8.),
+        ("decline".into(), 20.),
+      ]
+    );
+    let steps = list
+      .iter()
+      .map(|step| AdvancedStep::try_from(step).unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      steps.iter().map(|s| s.temperature).collect::<Vec<_>>(),
+      vec![95., 93., 92., 91.]
+    );
+    assert_eq!(steps[2].flow, 2.2);
+    assert_eq!(steps[2].max_flow_or_pressure, 9.);
+    assert_eq!(steps[3].transition, TransitionType::Smooth);
+  }
+
+  #[test]
+  fn test_presets() {
+    let pressure = ProfileDocument::parse(include_str!("../fixtures/profile.pressure")).unwrap();
+    assert_eq!(
+      names(&pressure.shot_steps()),
+      vec![
+        ("preinfusion temp boost".into(), 2.),
+        ("preinfusion".into(), 18.),
+        ("rise and hold".into(), 10.),
+        ("decline".into(), 25.),
+      ]
+    );
+    let flow = ProfileDocument::parse(include_str!("../fixtures/profile.flow")).unwrap();
+    assert_eq!(
+      names(&flow.shot_steps()),
+      vec![
+        ("preinfusion".into(), 8.),
+        ("hold".into(), 10.),
+        ("decline".into(), 20.),
+      ]
+    );
+
+    for text in fixture_profiles() {
+      let doc = ProfileDocument::parse(text).unwrap();
+      assert!(!doc.shot_steps().is_empty(), "{:?}", doc.title);
+    }
+  }
+}
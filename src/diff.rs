@@ -0,0 +1,268 @@
+use crate::parser::{Prop, Step};
+use crate::serializer;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PropChange {
+  pub name: String,
+  pub before: Option<String>,
+  pub after: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+  Added,
+  Removed,
+  // the step moved relative to the other matched steps
+  Moved,
+  Changed,
+  Same,
+}
+
+// One row of a diff: a step of the old profile, of the new one, or both
+// when they were aligned.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StepDiff {
+  pub before: Option<usize>,
+  pub after: Option<usize>,
+  pub change: Change,
+  pub props: Vec<PropChange>,
+}
+
+fn name(step: &Step) -> Option<String> {
+  step.0.iter().find_map(|prop| match prop {
+    Prop::Name(name) if !name.is_empty() => Some(name.clone()),
+    _ => None,
+  })
+}
+
+// Pairs steps with the same name first, in order of appearance so repeated
+// names pair up one by one; steps still unpaired at the same position pair
+// up after that.
+fn align(before: &[Step], after: &[Step]) -> Vec<(usize, usize)> {
+  let mut pairs = vec![];
+  let mut taken = vec![false; after.len()];
+  let mut paired = vec![false; before.len()];
+  for (i, step) in before.iter().enumerate() {
+    let wanted = match name(step) {
+      Some(wanted) => wanted,
+      None => continue,
+    };
+    let found = after
+      .iter()
+      .enumerate()
+      .position(|(j, other)| !taken[j] && name(other).as_ref() == Some(&wanted));
+    if let Some(j) = found {
+      taken[j] = true;
+      paired[i] = true;
+      pairs.push((i, j));
+    }
+  }
+  for i in 0..before.len().min(after.len()) {
+    if !paired[i] && !taken[i] {
+      taken[i] = true;
+      paired[i] = true;
+      pairs.push((i, i));
+    }
+  }
+  pairs.sort_unstable();
+  pairs
+}
+
+// Marks the pairs outside a longest run that keeps its order on both sides.
+fn moved(pairs: &[(usize, usize)]) -> Vec<bool> {
+  let n = pairs.len();
+  let mut length = vec![1; n];
+  let mut prev = vec![None; n];
+  for i in 0..n {
+    for j in 0..i {
+      if pairs[j].1 < pairs[i].1 && length[j] + 1 > length[i] {
+        length[i] = length[j] + 1;
+        prev[i] = Some(j);
+      }
+    }
+  }
+
+  let mut moved = vec![true; n];
+  let mut at = (0..n).max_by_key(|&i| (length[i], std::cmp::Reverse(i)));
+  while let Some(i) = at {
+    moved[i] = false;
+    at = prev[i];
+  }
+  moved
+}
+
+fn props(before: &Step, after: &Step) -> Vec<PropChange> {
+  let find = |step: &Step, name: &str| {
+    step
+      .0
+      .iter()
+      .find(|prop| prop.name() == name)
+      .map(serializer::value)
+  };
+  let mut names: Vec<&str> = vec![];
+  for prop in before.0.iter().chain(after.0.iter()) {
+    if !names.contains(&prop.name()) {
+      names.push(prop.name());
+    }
+  }
+
+  names
+    .into_iter()
+    .filter_map(|name| {
+      let (before, after) = (find(before, name), find(after, name));
+      if before == after {
+        return None;
+      }
+      Some(PropChange {
+        name: name.into(),
+        before,
+        after,
+      })
+    })
+    .collect()
+}
+
+// Rows follow the new profile; removed steps come right after the step that
+// preceded them in the old one.
+pub fn diff(before: &[Step], after: &[Step]) -> Vec<StepDiff> {
+  let pairs = align(before, after);
+  let moved = moved(&pairs);
+
+  let mut rows = vec![];
+  for (&(i, j), moved) in pairs.iter().zip(moved) {
+    let props = props(&before[i], &after[j]);
+    let change = if moved {
+      Change::Moved
+    } else if props.is_empty() {
+      Change::Same
+    } else {
+      Change::Changed
+    };
+    rows.push(((j as isize, 0, i), Some(i), Some(j), change, props));
+  }
+  for (j, step) in after.iter().enumerate() {
+    if !pairs.iter().any(|&(_, b)| b == j) {
+      let props = props(&Step(vec![]), step);
+      rows.push(((j as isize, 0, 0), None, Some(j), Change::Added, props));
+    }
+  }
+  for (i, step) in before.iter().enumerate() {
+    if !pairs.iter().any(|&(a, _)| a == i) {
+      let anchor = pairs
+        .iter()
+        .filter(|&&(a, _)| a < i)
+        .max()
+        .map_or(-1, |&(_, b)| b as isize);
+      let props = props(step, &Step(vec![]));
+      rows.push(((anchor, 1, i), Some(i), None, Change::Removed, props));
+    }
+  }
+  rows.sort_by_key(|row| row.0);
+
+  rows
+    .into_iter()
+    .map(|(_, before, after, change, props)| StepDiff {
+      before,
+      after,
+      change,
+      props,
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::parse;
+
+  fn changes(before: &str, after: &str) -> Vec<(Option<usize>, Option<usize>, Change)> {
+    diff(&parse(before).unwrap(), &parse(after).unwrap())
+      .into_iter()
+      .map(|row| (row.before, row.after, row.change))
+      .collect()
+  }
+
+  #[test]
+  fn test_props() {
+    let rows = diff(
+      &parse("{name fill flow 4 seconds 20}").unwrap(),
+      &parse("{name fill flow 6 seconds 20 weight 2}").unwrap(),
+    );
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].change, Change::Changed);
+    assert_eq!(
+      rows[0].props,
+      vec![
+        PropChange {
+          name: "flow".into(),
+          before: Some("4".into()),
+          after: Some("6".into()),
+        },
+        PropChange {
+          name: "weight".into(),
+          before: None,
+          after: Some("2".into()),
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn test_align_by_name() {
+    assert_eq!(
+      changes(
+        "{name fill} {name infuse} {name pour}",
+        "{name fill} {name pour}"
+      ),
+      vec![
+        (Some(0), Some(0), Change::Same),
+        (Some(1), None, Change::Removed),
+        (Some(2), Some(1), Change::Same),
+      ]
+    );
+    assert_eq!(
+      changes(
+        "{name fill} {name pour}",
+        "{name fill} {name bloom} {name pour}"
+      ),
+      vec![
+        (Some(0), Some(0), Change::Same),
+        (None, Some(1), Change::Added),
+        (Some(1), Some(2), Change::Same),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_align_by_position() {
+    assert_eq!(
+      changes("{flow 2} {flow 4}", "{flow 3} {flow 4}"),
+      vec![
+        (Some(0), Some(0), Change::Changed),
+        (Some(1), Some(1), Change::Same),
+      ]
+    );
+    assert_eq!(
+      changes("{name a flow 2} {flow 4}", "{name b flow 2} {flow 4}"),
+      vec![
+        (Some(0), Some(0), Change::Changed),
+        (Some(1), Some(1), Change::Same),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_reordered() {
+    assert_eq!(
+      changes(
+        "{name fill} {name bloom} {name pour}",
+        "{name bloom} {name fill} {name pour}"
+      ),
+      vec![
+        (Some(1), Some(0), Change::Moved),
+        (Some(0), Some(1), Change::Same),
+        (Some(2), Some(2), Change::Same),
+      ]
+    );
+  }
+}
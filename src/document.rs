@@ -0,0 +1,469 @@
+use std::fmt;
+
+use nom::bytes::complete::take_while1;
+use nom::character::complete::space1;
+use nom::sequence::tuple;
+use nom::IResult;
+
+use crate::convert;
+use crate::parser::{number, parse, string_val, Diagnostic, Step};
+use crate::serializer;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProfileType {
+  Pressure,
+  Flow,
+  Advanced,
+  Other(String),
+}
+
+impl ProfileType {
+  pub fn parse(value: &str) -> Self {
+    match value {
+      "settings_2a" => ProfileType::Pressure,
+      "settings_2b" => ProfileType::Flow,
+      "settings_2c" => ProfileType::Advanced,
+      _ => ProfileType::Other(value.into()),
+    }
+  }
+
+  pub fn as_str(&self) -> &str {
+    match self {
+      ProfileType::Pressure => "settings_2a",
+      ProfileType::Flow => "settings_2b",
+      ProfileType::Advanced => "settings_2c",
+      ProfileType::Other(value) => value,
+    }
+  }
+}
+
+// The parameters behind the simple pressure (`settings_2a`) and flow
+// (`settings_2b`) profile editors.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SimpleSettings {
+  pub espresso_temperature: Option<f64>,
+  pub espresso_temperature_0: Option<f64>,
+  pub espresso_temperature_1: Option<f64>,
+  pub espresso_temperature_2: Option<f64>,
+  pub espresso_temperature_3: Option<f64>,
+  pub preinfusion_time: Option<f64>,
+  pub preinfusion_flow_rate: Option<f64>,
+  pub preinfusion_stop_pressure: Option<f64>,
+  pub preinfusion_guarantee: Option<bool>,
+  pub espresso_pressure: Option<f64>,
+  pub espresso_hold_time: Option<f64>,
+  pub espresso_decline_time: Option<f64>,
+  pub pressure_end: Option<f64>,
+  pub flow_profile_preinfusion: Option<f64>,
+  pub flow_profile_preinfusion_time: Option<f64>,
+  pub flow_profile_hold: Option<f64>,
+  pub flow_profile_hold_time: Option<f64>,
+  pub flow_profile_decline: Option<f64>,
+  pub flow_profile_decline_time: Option<f64>,
+  pub flow_profile_minimum_pressure: Option<f64>,
+  pub maximum_pressure: Option<f64>,
+  pub maximum_flow: Option<f64>,
+  pub maximum_pressure_range_default: Option<f64>,
+  pub maximum_flow_range_default: Option<f64>,
+  pub final_desired_shot_weight: Option<f64>,
+  pub final_desired_shot_volume: Option<f64>,
+  pub water_temperature: Option<f64>,
+}
+
+// A whole DE1 `.tcl` profile. Every key the app writes has a typed field;
+// anything else is kept in `extra`. `order` remembers the keys as they were
+// read, so a document writes back in the same layout, and `blank` the number
+// keys that were read empty.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProfileDocument {
+  pub title: Option<String>,
+  pub author: Option<String>,
+  pub notes: Option<String>,
+  pub beverage_type: Option<String>,
+  pub language: Option<String>,
+  pub hide: Option<bool>,
+  pub profile_type: Option<ProfileType>,
+  pub steps: Option<Vec<Step>>,
+  pub tank_temperature: Option<f64>,
+  pub target_weight: Option<f64>,
+  pub target_volume: Option<f64>,
+  pub target_volume_count_start: Option<f64>,
+  pub temperature_steps_enabled: Option<bool>,
+  pub maximum_pressure_range_advanced: Option<f64>,
+  pub maximum_flow_range_advanced: Option<f64>,
+  pub simple: SimpleSettings,
+  pub extra: Vec<(String, String)>,
+  order: Vec<String>,
+  blank: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DocumentError {
+  Syntax(usize),
+  Value(String, &'static str),
+  Steps(Diagnostic),
+}
+
+impl fmt::Display for DocumentError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      DocumentError::Syntax(offset) => write!(f, "byte {}: expected `key value`", offset),
+      DocumentError::Value(key, expected) => write!(f, "`{}`: expected {}", key, expected),
+      DocumentError::Steps(diagnostic) => write!(f, "`advanced_shot`: {}", diagnostic),
+    }
+  }
+}
+
+enum Field<'a> {
+  Text(&'a mut Option<String>),
+  Number(&'a mut Option<f64>),
+  Flag(&'a mut Option<bool>),
+  Type(&'a mut Option<ProfileType>),
+  Steps(&'a mut Option<Vec<Step>>),
+}
+
+// Typed keys in the order a new document writes them.
+const KEYS: &[&str] = &[
+  "profile_title",
+  "author",
+  "profile_notes",
+  "beverage_type",
+  "settings_profile_type",
+  "advanced_shot",
+  "tank_desired_water_temperature",
+  "final_desired_shot_weight_advanced",
+  "final_desired_shot_volume_advanced",
+  "final_desired_shot_volume_advanced_count_start",
+  "espresso_temperature_steps_enabled",
+  "maximum_pressure_range_advanced",
+  "maximum_flow_range_advanced",
+  "espresso_temperature",
+  "espresso_temperature_0",
+  "espresso_temperature_1",
+  "espresso_temperature_2",
+  "espresso_temperature_3",
+  "preinfusion_time",
+  "preinfusion_flow_rate",
+  "preinfusion_stop_pressure",
+  "preinfusion_guarantee",
+  "espresso_pressure",
+  "espresso_hold_time",
+  "espresso_decline_time",
+  "pressure_end",
+  "flow_profile_preinfusion",
+  "flow_profile_preinfusion_time",
+  "flow_profile_hold",
+  "flow_profile_hold_time",
+  "flow_profile_decline",
+  "flow_profile_decline_time",
+  "flow_profile_minimum_pressure",
+  "maximum_pressure",
+  "maximum_flow",
+  "maximum_pressure_range_default",
+  "maximum_flow_range_default",
+  "final_desired_shot_weight",
+  "final_desired_shot_volume",
+  "water_temperature",
+  "profile_language",
+  "profile_hide",
+];
+
+fn key(i: &[u8]) -> IResult<&[u8], &[u8]> {
+  take_while1(|c: u8| c.is_ascii_alphanumeric() || c == b'_')(i)
+}
+
+fn entry(i: &[u8]) -> IResult<&[u8], (&[u8], String)> {
+  let (i, (key, _, value)) = tuple((key, space1, string_val))(i)?;
+  Ok((i, (key, value)))
+}
+
+fn skip_space(i: &[u8]) -> &[u8] {
+  let n = i.iter().take_while(|c| c.is_ascii_whitespace()).count();
+  &i[n..]
+}
+
+impl ProfileDocument {
+  fn field(&mut self, key: &str) -> Option<Field<'_>> {
+    let simple = &mut self.simple;
+    let field = match key {
+      "profile_title" => Field::Text(&mut self.title),
+      "author" => Field::Text(&mut self.author),
+      "profile_notes" => Field::Text(&mut self.notes),
+      "beverage_type" => Field::Text(&mut self.beverage_type),
+      "profile_language" => Field::Text(&mut self.language),
+      "profile_hide" => Field::Flag(&mut self.hide),
+      "settings_profile_type" => Field::Type(&mut self.profile_type),
+      "advanced_shot" => Field::Steps(&mut self.steps),
+      "tank_desired_water_temperature" => Field::Number(&mut self.tank_temperature),
+      "final_desired_shot_weight_advanced" => Field::Number(&mut self.target_weight),
+      "final_desired_shot_volume_advanced" => Field::Number(&mut self.target_volume),
+      "final_desired_shot_volume_advanced_count_start" => {
+        Field::Number(&mut self.target_volume_count_start)
+      }
+      "espresso_temperature_steps_enabled" => Field::Flag(&mut self.temperature_steps_enabled),
+      "maximum_pressure_range_advanced" => {
+        Field::Number(&mut self.maximum_pressure_range_advanced)
+      }
+      "maximum_flow_range_advanced" => Field::Number(&mut self.maximum_flow_range_advanced),
+      "espresso_temperature" => Field::Number(&mut simple.espresso_temperature),
+      "espresso_temperature_0" => Field::Number(&mut simple.espresso_temperature_0),
+      "espresso_temperature_1" => Field::Number(&mut simple.espresso_temperature_1),
+      "espresso_temperature_2" => Field::Number(&mut simple.espresso_temperature_2),
+      "espresso_temperature_3" => Field::Number(&mut simple.espresso_temperature_3),
+      "preinfusion_time" => Field::Number(&mut simple.preinfusion_time),
+      "preinfusion_flow_rate" => Field::Number(&mut simple.preinfusion_flow_rate),
+      "preinfusion_stop_pressure" => Field::Number(&mut simple.preinfusion_stop_pressure),
+      "preinfusion_guarantee" => Field::Flag(&mut simple.preinfusion_guarantee),
+      "espresso_pressure" => Field::Number(&mut simple.espresso_pressure),
+      "espresso_hold_time" => Field::Number(&mut simple.espresso_hold_time),
+      "espresso_decline_time" => Field::Number(&mut simple.espresso_decline_time),
+      "pressure_end" => Field::Number(&mut simple.pressure_end),
+      "flow_profile_preinfusion" => Field::Number(&mut simple.flow_profile_preinfusion),
+      "flow_profile_preinfusion_time" => Field::Number(&mut simple.flow_profile_preinfusion_time),
+      "flow_profile_hold" => Field::Number(&mut simple.flow_profile_hold),
+      "flow_profile_hold_time" => Field::Number(&mut simple.flow_profile_hold_time),
+      "flow_profile_decline" => Field::Number(&mut simple.flow_profile_decline),
+      "flow_profile_decline_time" => Field::Number(&mut simple.flow_profile_decline_time),
+      "flow_profile_minimum_pressure" => Field::Number(&mut simple.flow_profile_minimum_pressure),
+      "maximum_pressure" => Field::Number(&mut simple.maximum_pressure),
+      "maximum_flow" => Field::Number(&mut simple.maximum_flow),
+      "maximum_pressure_range_default" => Field::Number(&mut simple.maximum_pressure_range_default),
+      "maximum_flow_range_default" => Field::Number(&mut simple.maximum_flow_range_default),
+      "final_desired_shot_weight" => Field::Number(&mut simple.final_desired_shot_weight),
+      "final_desired_shot_volume" => Field::Number(&mut simple.final_desired_shot_volume),
+      "water_temperature" => Field::Number(&mut simple.water_temperature),
+      _ => return None,
+    };
+    Some(field)
+  }
+
+  fn set(&mut self, key: &str, value: String) -> Result<(), DocumentError> {
+    let invalid = |expected| DocumentError::Value(key.into(), expected);
+    self.blank.retain(|k| k != key);
+    match self.field(key) {
+      Some(Field::Text(field)) => *field = Some(value),
+      // some app versions leave unused numbers empty
+      Some(Field::Number(field)) if value.is_empty() => {
+        *field = None;
+        self.blank.push(key.into());
+      }
+      Some(Field::Number(field)) => *field = Some(number(&value).ok_or_else(|| invalid("a number"))?),
+      Some(Field::Flag(field)) => {
+        *field = match value.as_str() {
+          "0" => Some(false),
+          "1" => Some(true),
+          _ => return Err(invalid("`0` or `1`")),
+        }
+      }
+      Some(Field::Type(field)) => *field = Some(ProfileType::parse(&value)),
+      Some(Field::Steps(field)) => *field = Some(parse(&value).map_err(DocumentError::Steps)?),
+      None => match self.extra.iter_mut().find(|(k, _)| k == key) {
+        Some((_, v)) => *v = value,
+        None => self.extra.push((key.into(), value)),
+      },
+    }
+    Ok(())
+  }
+
+  fn value(&mut self, key: &str) -> Option<String> {
+    let blank = self.blank.iter().any(|k| k == key);
+    let value = match self.field(key) {
+      Some(Field::Text(field)) => serializer::word(field.as_ref()?),
+      Some(Field::Number(field)) => match field {
+        Some(v) => serializer::number(*v),
+        None if blank => serializer::word(""),
+        None => return None,
+      },
+      Some(Field::Flag(field)) => if (*field)? { "1" } else { "0" }.into(),
+      Some(Field::Type(field)) => serializer::word(field.as_ref()?.as_str()),
+      Some(Field::Steps(field)) => format!("{{{}}}", serializer::steps(field.as_ref()?)),
+      None => {
+        let (_, v) = self.extra.iter().find(|(k, _)| k == key)?;
+        serializer::word(v)
+      }
+    };
+    Some(value)
+  }
+
+  // The steps the DE1 runs for this profile; simple pressure and flow
+  // profiles are converted the way the app does it.
+  pub fn shot_steps(&self) -> Vec<Step> {
+    let temperature_steps = self.temperature_steps_enabled.unwrap_or_default();
+    match self.profile_type {
+      Some(ProfileType::Pressure) => convert::pressure_steps(&self.simple, temperature_steps),
+      Some(ProfileType::Flow) => convert::flow_steps(&self.simple, temperature_steps),
+      _ => self.steps.clone().unwrap_or_default(),
+    }
+  }
+
+  pub fn parse(text: &str) -> Result<Self, DocumentError> {
+    let input = text.as_bytes();
+    let mut doc = Self::default();
+    let mut i = skip_space(input);
+    while !i.is_empty() {
+      let (rest, (key, value)) =
+        entry(i).map_err(|_| DocumentError::Syntax(input.len() - i.len()))?;
+      let key = String::from_utf8_lossy(key).into_owned();
+      doc.set(&key, value)?;
+      if !doc.order.contains(&key) {
+        doc.order.push(key);
+      }
+      i = skip_space(rest);
+    }
+    Ok(doc)
+  }
+
+  pub fn write(&self) -> String {
+    let mut doc = self.clone();
+    let mut keys = self.order.clone();
+    for key in KEYS.iter().map(|k| k.to_string()) {
+      if !keys.contains(&key) {
+        keys.push(key);
+      }
+    }
+    for (key, _) in &self.extra {
+      if !keys.contains(key) {
+        keys.push(key.clone());
+      }
+    }
+
+    let mut out = String::new();
+    for key in keys {
+      if let Some(value) = doc.value(&key) {
+        out.push_str(&format!("{} {}\n", key, value));
+      }
+    }
+    out
+  }
+
+  // The app lists profiles by file name, so anything but ASCII letters and
+  // digits in the title becomes `_`.
+  pub fn file_name(&self) -> String {
+    let title = self.title.as_deref().unwrap_or_default().trim();
+    if title.is_empty() {
+      return "profile.tcl".into();
+    }
+    let stem: String = title
+      .chars()
+      .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+      .collect();
+    format!("{}.tcl", stem)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::Prop;
+
+  const TCL: &str = "advanced_shot {{name {Pressure Up} seconds 4.00} {flow 2 seconds 30}}
+author Decent
+profile_notes {Fill, then \"bloom\".
+Second {line}.}
+settings_profile_type settings_2c
+final_desired_shot_weight_advanced 36
+espresso_temperature_steps_enabled 0
+espresso_pressure 8.6
+maximum_flow {}
+profile_title {Best practice}
+profile_vendor acme
+";
+
+  #[test]
+  fn test_parse() {
+    let doc = ProfileDocument::parse(TCL).unwrap();
+    assert_eq!(doc.title.as_deref(), Some("Best practice"));
+    assert_eq!(doc.author.as_deref(), Some("Decent"));
+    assert_eq!(
+      doc.notes.as_deref(),
+      Some("Fill, then \"bloom\".\nSecond {line}.")
+    );
+    assert_eq!(doc.profile_type, Some(ProfileType::Advanced));
+    assert_eq!(doc.target_weight, Some(36.0));
+    assert_eq!(doc.temperature_steps_enabled, Some(false));
+    assert_eq!(doc.simple.espresso_pressure, Some(8.6));
+    assert_eq!(doc.simple.maximum_flow, None);
+    assert_eq!(
+      doc.steps,
+      Some(vec![
+        Step(vec![Prop::Name("Pressure Up".into()), Prop::Seconds(4.0)]),
+        Step(vec![Prop::Flow(2.0), Prop::Seconds(30.0)]),
+      ])
+    );
+    assert_eq!(doc.extra, vec![("profile_vendor".into(), "acme".into())]);
+  }
+
+  #[test]
+  fn test_parse_errors() {
+    assert_eq!(
+      ProfileDocument::parse("author Decent\n{oops}"),
+      Err(DocumentError::Syntax(14))
+    );
+    assert_eq!(
+      ProfileDocument::parse("espresso_pressure high"),
+      Err(DocumentError::Value("espresso_pressure".into(), "a number"))
+    );
+    assert!(matches!(
+      ProfileDocument::parse("advanced_shot {{flow x}}"),
+      Err(DocumentError::Steps(_))
+    ));
+  }
+
+  #[test]
+  fn test_write() {
+    let doc = ProfileDocument::parse(TCL).unwrap();
+    assert_eq!(
+      doc.write(),
+      "advanced_shot {{name {Pressure Up} seconds 4} {flow 2 seconds 30}}
+author Decent
+profile_notes {Fill, then \"bloom\".
+Second {line}.}
+settings_profile_type settings_2c
+final_desired_shot_weight_advanced 36
+espresso_temperature_steps_enabled 0
+espresso_pressure 8.6
+maximum_flow {}
+profile_title {Best practice}
+profile_vendor acme
+"
+    );
+    assert_eq!(ProfileDocument::parse(&doc.write()), Ok(doc));
+
+    let doc = ProfileDocument {
+      title: Some("New".into()),
+      profile_type: Some(ProfileType::Advanced),
+      steps: Some(vec![]),
+      ..ProfileDocument::default()
+    };
+    assert_eq!(
+      doc.write(),
+      "profile_title New\nsettings_profile_type settings_2c\nadvanced_shot {}\n"
+    );
+  }
+
+  #[test]
+  fn test_file_name() {
+    let doc = |title: &str| ProfileDocument {
+      title: Some(title.into()),
+      ..ProfileDocument::default()
+    };
+    assert_eq!(doc("Best practice").file_name(), "Best_practice.tcl");
+    assert_eq!(
+      doc("Blooming/Allongé 2.0").file_name(),
+      "Blooming_Allong__2_0.tcl"
+    );
+    assert_eq!(doc(" ").file_name(), "profile.tcl");
+    assert_eq!(ProfileDocument::default().file_name(), "profile.tcl");
+  }
+
+  #[test]
+  fn test_round_trip_presets() {
+    use crate::profile::fixture_profiles;
+
+    for text in fixture_profiles() {
+      let doc = ProfileDocument::parse(text).unwrap();
+      assert_ne!(doc, ProfileDocument::default());
+      assert_eq!(ProfileDocument::parse(&doc.write()), Ok(doc));
+    }
+  }
+}
@@ -0,0 +1,240 @@
+use crate::parser::PumpType;
+use crate::profile::{PuckModel, Pump};
+use crate::step::{AdvancedStep, MAX_SECONDS};
+use crate::timeline::{Series, Timeline};
+
+// Integration step in seconds.
+const TICK: f64 = 0.1;
+// Slack for volumes summed up tick by tick.
+const EPSILON: f64 = 1e-6;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+  pub time: f64,
+  // ml pumped since the start
+  pub volume: f64,
+  // g in the cup
+  pub weight: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopKind {
+  // a step's `volume` limit
+  Volume,
+  // a step's `weight` exit
+  Weight,
+  // the profile's target weight ends the shot
+  TargetWeight,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stop {
+  pub step: Option<usize>,
+  pub time: f64,
+  pub kind: StopKind,
+}
+
+// Water and beverage over the full-length timeline, pumped the way
+// `simulate` does it but without ending steps early. Like `simulate` it
+// stops after `MAX_SECONDS`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Estimate {
+  pub points: Vec<Point>,
+  pub stops: Vec<Stop>,
+}
+
+// The target the step's pump aims for at `t`.
+fn setpoint(step: &AdvancedStep, timeline: &Timeline, t: f64) -> f64 {
+  let series = match step.pump {
+    PumpType::Pressure => Series::Pressure,
+    PumpType::Flow => Series::Flow,
+  };
+  timeline.value_at(series, t).unwrap_or_default()
+}
+
+pub fn estimate(
+  steps: &[AdvancedStep],
+  timeline: &Timeline,
+  puck: &PuckModel,
+  target_weight: Option<f64>,
+) -> Estimate {
+  let mut estimate = Estimate {
+    points: vec![Point {
+      time: 0.,
+      volume: 0.,
+      weight: 0.,
+    }],
+    stops: vec![],
+  };
+  let mut pump = Pump::default();
+
+  for (index, (step, &(start, end))) in steps.iter().zip(timeline.spans.iter()).enumerate() {
+    let step_start_volume = pump.volume;
+    let mut volume_stop = step.volume <= 0.;
+    let mut weight_stop = step.weight <= 0.;
+    let end = end.min(MAX_SECONDS);
+    let mut time = start;
+    while time < end {
+      let tick = TICK.min(end - time);
+      let setpoint = setpoint(step, timeline, time + tick / 2.);
+      time += tick;
+      pump.tick(step, setpoint, time, tick, puck);
+      let volume = pump.volume;
+      let weight = puck.weight(volume);
+      estimate.points.push(Point {
+        time,
+        volume,
+        weight,
+      });
+
+      let mut stop = |kind| {
+        estimate.stops.push(Stop {
+          step: Some(index),
+          time,
+          kind,
+        })
+      };
+      if !volume_stop && volume - step_start_volume + EPSILON >= step.volume {
+        volume_stop = true;
+        stop(StopKind::Volume);
+      }
+      if !weight_stop && weight + EPSILON >= step.weight {
+        weight_stop = true;
+        stop(StopKind::Weight);
+      }
+    }
+  }
+
+  if let Some(target) = target_weight.filter(|v| *v > 0.) {
+    let reached = estimate
+      .points
+      .iter()
+      .find(|point| point.weight + EPSILON >= target);
+    if let Some(point) = reached {
+      estimate.stops.push(Stop {
+        step: None,
+        time: point.time,
+        kind: StopKind::TargetWeight,
+      });
+    }
+  }
+  estimate
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::profile::simulate;
+
+  fn flow(v: f64, seconds: f64) -> AdvancedStep {
+    AdvancedStep {
+      pump: PumpType::Flow,
+      flow: v,
+      seconds,
+      ..AdvancedStep::default()
+    }
+  }
+
+  fn run(steps: &[AdvancedStep], target_weight: Option<f64>) -> Estimate {
+    estimate(
+      steps,
+      &Timeline::new(steps),
+      &PuckModel::default(),
+      target_weight,
+    )
+  }
+
+  fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-6
+  }
+
+  #[test]
+  fn test_volume() {
+    let estimate = run(&[flow(2., 10.), flow(4., 5.)], None);
+    let last = estimate.points.last().unwrap();
+    assert!(close(last.time, 15.));
+    assert!(close(last.volume, 40.));
+    // 40 ml less 6 ml headspace and 18 g * 1.1 ml/g
+    assert!(close(last.weight, 14.2));
+    assert!(estimate.stops.is_empty());
+  }
+
+  #[test]
+  fn test_max_seconds() {
+    let estimate = run(&[flow(2., 3000.), flow(2., 3000.)], None);
+    let last = estimate.points.last().unwrap();
+    assert!(close(last.time, MAX_SECONDS));
+    // a point per tick, plus the odd short tick where float times fall short
+    assert!(estimate.points.len() < 36010);
+  }
+
+  #[test]
+  fn test_pressure_flow() {
+    let puck = PuckModel {
+      initial_resistance: 3.,
+      final_resistance: 3.,
+      ..PuckModel::default()
+    };
+    let steps = [AdvancedStep {
+      pressure: 6.,
+      seconds: 10.,
+      ..AdvancedStep::default()
+    }];
+    let estimate = estimate(&steps, &Timeline::new(&steps), &puck, None);
+    // 8 ticks at full pump flow fill the 6 ml headspace, then 2 ml/s
+    assert!(close(
+      estimate.points.last().unwrap().volume,
+      6.4 + 9.2 * 2.
+    ));
+  }
+
+  #[test]
+  fn test_matches_simulation() {
+    let steps = [AdvancedStep {
+      pressure: 9.,
+      seconds: 30.,
+      ..AdvancedStep::default()
+    }];
+    for resistance in [(4., 2.), (0.2, 0.2), (0., 0.)].iter() {
+      let puck = PuckModel {
+        initial_resistance: resistance.0,
+        final_resistance: resistance.1,
+        ..PuckModel::default()
+      };
+      let estimate = estimate(&steps, &Timeline::new(&steps), &puck, None);
+      let simulation = simulate(&steps, &puck);
+      assert!(close(
+        estimate.points.last().unwrap().volume,
+        simulation.samples.last().unwrap().volume
+      ));
+    }
+  }
+
+  #[test]
+  fn test_stops() {
+    let steps = [
+      AdvancedStep {
+        volume: 10.,
+        ..flow(2., 10.)
+      },
+      AdvancedStep {
+        weight: 2.,
+        ..flow(2., 20.)
+      },
+    ];
+    let estimate = run(&steps, Some(10.));
+    let stops = estimate
+      .stops
+      .iter()
+      .map(|stop| (stop.step, stop.kind, (stop.time * 10.).round() / 10.))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      stops,
+      vec![
+        (Some(0), StopKind::Volume, 5.),
+        (Some(1), StopKind::Weight, 13.9),
+        (None, StopKind::TargetWeight, 17.9),
+      ]
+    );
+  }
+}
@@ -0,0 +1,496 @@
+use std::convert::TryFrom;
+
+use serde_json::{json, Map, Value};
+
+use crate::document::{ProfileDocument, ProfileType};
+use crate::parser::{
+  number, Diagnostic, ExitType, Position, Prop, PumpType, SensorType, Step, TransitionType,
+};
+use crate::serializer;
+use crate::step::AdvancedStep;
+
+// Decent's JSON v2 profile format, as written by newer DE1 app versions and
+// visualizer.coffee. Numbers are written as strings, like the app does, but
+// either form is read.
+
+pub fn is_json(text: &str) -> bool {
+  let text = text.trim_start();
+  text.starts_with('{') && text[1..].trim_start().starts_with('"')
+}
+
+fn error(step: Option<usize>, prop: &str, expected: &str) -> Diagnostic {
+  Diagnostic {
+    position: None,
+    step,
+    prop: Some(prop.into()),
+    expected: expected.into(),
+  }
+}
+
+fn text(value: &Value) -> Option<String> {
+  match value {
+    Value::String(v) => Some(v.clone()),
+    Value::Number(v) => Some(v.to_string()),
+    Value::Bool(v) => Some(if *v { "1" } else { "0" }.into()),
+    _ => None,
+  }
+}
+
+fn float(value: &Value) -> Option<f64> {
+  match value {
+    Value::Number(v) => v.as_f64(),
+    Value::String(v) => number(v),
+    _ => None,
+  }
+}
+
+fn read_exit(step: usize, exit: &Value) -> Result<Vec<Prop>, Diagnostic> {
+  let kind = exit.get("type").and_then(Value::as_str);
+  let condition = exit.get("condition").and_then(Value::as_str);
+  let exit_type = match (kind, condition) {
+    (Some("pressure"), Some("over")) => ExitType::PressureOver,
+    (Some("pressure"), Some("under")) => ExitType::PressureUnder,
+    (Some("flow"), Some("over")) => ExitType::FlowOver,
+    (Some("flow"), Some("under")) => ExitType::FlowUnder,
+    _ => {
+      return Err(error(
+        Some(step),
+        "exit",
+        "`type` pressure or flow and `condition` over or under",
+      ))
+    }
+  };
+  let v = exit
+    .get("value")
+    .and_then(float)
+    .ok_or_else(|| error(Some(step), "exit", "a number as `value`"))?;
+  let threshold = match exit_type {
+    ExitType::PressureOver => Prop::ExitPressureOver(v),
+    ExitType::PressureUnder => Prop::ExitPressureUnder(v),
+    ExitType::FlowOver => Prop::ExitFlowOver(v),
+    ExitType::FlowUnder => Prop::ExitFlowUnder(v),
+  };
+  Ok(vec![
+    Prop::ExitIf(true),
+    Prop::ExitType(exit_type),
+    threshold,
+  ])
+}
+
+fn read_step(index: usize, value: &Value) -> Result<Step, Diagnostic> {
+  let object = value
+    .as_object()
+    .ok_or_else(|| error(Some(index), "steps", "an object per step"))?;
+
+  let mut list = vec![];
+  let mut has_exit = false;
+  for (key, value) in object {
+    let num = || float(value).ok_or_else(|| error(Some(index), key, "a number"));
+    let word = |expected| {
+      text(value)
+        .map(|v| v.into_bytes())
+        .ok_or_else(|| error(Some(index), key, expected))
+    };
+    let prop = match key.as_str() {
+      "name" => Prop::Name(text(value).unwrap_or_default()),
+      "temperature" => Prop::Temperature(num()?),
+      "pressure" => Prop::Pressure(num()?),
+      "flow" => Prop::Flow(num()?),
+      "seconds" => Prop::Seconds(num()?),
+      "volume" => Prop::Volume(num()?),
+      "weight" => Prop::Weight(num()?),
+      "sensor" => Prop::Sensor(
+        SensorType::try_from(&word("`coffee` or `water`")?[..])
+          .map_err(|_| error(Some(index), key, "`coffee` or `water`"))?,
+      ),
+      "pump" => Prop::Pump(
+        PumpType::try_from(&word("`flow` or `pressure`")?[..])
+          .map_err(|_| error(Some(index), key, "`flow` or `pressure`"))?,
+      ),
+      "transition" => Prop::Transition(
+        TransitionType::try_from(&word("`fast` or `smooth`")?[..])
+          .map_err(|_| error(Some(index), key, "`fast` or `smooth`"))?,
+      ),
+      "exit" => {
+        has_exit = true;
+        list.extend(read_exit(index, value)?);
+        continue;
+      }
+      "limiter" => {
+        let v = value.get("value").and_then(float);
+        let range = value.get("range").and_then(float);
+        match (v, range) {
+          (Some(v), Some(range)) => {
+            list.push(Prop::MaxFlowOrPressure(v));
+            list.push(Prop::MaxFlowOrPressureRange(range));
+          }
+          _ => return Err(error(Some(index), key, "numbers as `value` and `range`")),
+        }
+        continue;
+      }
+      _ => match text(value) {
+        Some(v) => Prop::Unknown((key.clone(), v)),
+        None => continue,
+      },
+    };
+    list.push(prop);
+  }
+
+  if !has_exit {
+    list.push(Prop::ExitIf(false));
+  }
+  let step = Step(list);
+  // the same checks the Tcl parser runs, so no step drops off silently
+  AdvancedStep::try_from(&step)
+    .map_err(|err| error(Some(index), step.0[err.index].name(), err.expected()))?;
+  Ok(step)
+}
+
+pub fn read(input: &str) -> Result<ProfileDocument, Diagnostic> {
+  let root: Value = serde_json::from_str(input).map_err(|e| {
+    // serde_json counts bytes from 1, with 0 right after a newline
+    let line_start = input
+      .split_inclusive('\n')
+      .take(e.line().saturating_sub(1))
+      .map(str::len)
+      .sum::<usize>();
+    let offset = line_start + e.column().saturating_sub(1);
+    Diagnostic {
+      position: Some(Position::at(input.as_bytes(), offset.min(input.len()))),
+      step: None,
+      prop: None,
+      expected: format!("valid JSON ({})", e),
+    }
+  })?;
+  let object = root
+    .as_object()
+    .ok_or_else(|| error(None, "profile", "an object"))?;
+
+  let mut doc = ProfileDocument::default();
+  let mut legacy = None;
+  for (key, value) in object {
+    let num = || float(value).ok_or_else(|| error(None, key, "a number"));
+    match key.as_str() {
+      "title" => doc.title = text(value),
+      "author" => doc.author = text(value),
+      "notes" => doc.notes = text(value),
+      "beverage_type" => doc.beverage_type = text(value),
+      "lang" => doc.language = text(value),
+      "hidden" => doc.hide = text(value).map(|v| v == "1" || v == "true"),
+      "tank_temperature" => doc.tank_temperature = Some(num()?),
+      "target_weight" => doc.target_weight = Some(num()?),
+      "target_volume" => doc.target_volume = Some(num()?),
+      "target_volume_count_start" => doc.target_volume_count_start = Some(num()?),
+      "legacy_profile_type" => legacy = text(value),
+      "steps" => {
+        let list = value
+          .as_array()
+          .ok_or_else(|| error(None, key, "an array of steps"))?;
+        let steps = list
+          .iter()
+          .enumerate()
+          .map(|(index, step)| read_step(index, step))
+          .collect::<Result<Vec<Step>, Diagnostic>>()?;
+        doc.steps = Some(steps);
+      }
+      // derived from the steps or only meaningful to the app
+      "version" | "type" | "changes_since_last_espresso" | "reference_file" => (),
+      _ => {
+        if let Some(v) = text(value) {
+          doc.extra.push((key.clone(), v));
+        }
+      }
+    }
+  }
+
+  // JSON profiles always carry their steps, so the simple editors' types read
+  // as advanced ones.
+  doc.profile_type = Some(match legacy.as_deref().map(ProfileType::parse) {
+    Some(ProfileType::Other(v)) => ProfileType::Other(v),
+    _ => ProfileType::Advanced,
+  });
+  Ok(doc)
+}
+
+fn num(v: f64) -> Value {
+  Value::String(serializer::number(v))
+}
+
+fn exit_type_val(v: ExitType) -> (&'static str, &'static str) {
+  match v {
+    ExitType::PressureOver => ("pressure", "over"),
+    ExitType::PressureUnder => ("pressure", "under"),
+    ExitType::FlowOver => ("flow", "over"),
+    ExitType::FlowUnder => ("flow", "under"),
+  }
+}
+
+fn write_step(index: usize, step: &Step) -> Result<Value, Diagnostic> {
+  let typed = AdvancedStep::try_from(step)
+    .map_err(|err| error(Some(index), step.0[err.index].name(), err.expected()))?;
+  let mut object = Map::new();
+  object.insert("name".into(), json!(typed.name));
+  object.insert("temperature".into(), num(typed.temperature));
+  object.insert(
+    "sensor".into(),
+    json!(match typed.sensor {
+      SensorType::Coffee => "coffee",
+      SensorType::Water => "water",
+    }),
+  );
+  object.insert(
+    "pump".into(),
+    json!(match typed.pump {
+      PumpType::Flow => "flow",
+      PumpType::Pressure => "pressure",
+    }),
+  );
+  object.insert(
+    "transition".into(),
+    json!(match typed.transition {
+      TransitionType::Fast => "fast",
+      TransitionType::Smooth => "smooth",
+    }),
+  );
+  object.insert("pressure".into(), num(typed.pressure));
+  object.insert("flow".into(), num(typed.flow));
+  object.insert("seconds".into(), num(typed.seconds));
+  object.insert("volume".into(), num(typed.volume));
+  object.insert("weight".into(), num(typed.weight));
+  if let Some((exit_type, v)) = typed.exit() {
+    let (kind, condition) = exit_type_val(exit_type);
+    object.insert(
+      "exit".into(),
+      json!({ "type": kind, "condition": condition, "value": num(v) }),
+    );
+  }
+  if step
+    .0
+    .iter()
+    .any(|p| matches!(p, Prop::MaxFlowOrPressure(_)))
+  {
+    object.insert(
+      "limiter".into(),
+      json!({
+        "value": num(typed.max_flow_or_pressure),
+        "range": num(typed.max_flow_or_pressure_range),
+      }),
+    );
+  }
+  for (key, v) in &typed.unknown {
+    object.insert(key.clone(), json!(v));
+  }
+  Ok(Value::Object(object))
+}
+
+// Fails on a step the Tcl parser would have rejected, rather than writing
+// something else in its place.
+pub fn write(doc: &ProfileDocument) -> Result<String, Diagnostic> {
+  let mut object = Map::new();
+  let mut text = |key: &str, v: &Option<String>| {
+    if let Some(v) = v {
+      object.insert(key.into(), json!(v));
+    }
+  };
+  text("title", &doc.title);
+  text("author", &doc.author);
+  text("notes", &doc.notes);
+  text("beverage_type", &doc.beverage_type);
+
+  let steps = doc.shot_steps();
+  let steps = steps
+    .iter()
+    .enumerate()
+    .map(|(index, step)| write_step(index, step))
+    .collect::<Result<Vec<_>, _>>()?;
+  object.insert("steps".into(), Value::Array(steps));
+
+  let numbers = [
+    ("tank_temperature", doc.tank_temperature),
+    ("target_weight", doc.target_weight),
+    ("target_volume", doc.target_volume),
+    ("target_volume_count_start", doc.target_volume_count_start),
+  ];
+  for (key, v) in numbers.iter() {
+    if let Some(v) = v {
+      object.insert(key.to_string(), num(*v));
+    }
+  }
+
+  let profile_type = doc.profile_type.clone().unwrap_or(ProfileType::Advanced);
+  object.insert("legacy_profile_type".into(), json!(profile_type.as_str()));
+  object.insert("type".into(), json!("advanced"));
+  if let Some(v) = &doc.language {
+    object.insert("lang".into(), json!(v));
+  }
+  if let Some(v) = doc.hide {
+    object.insert("hidden".into(), json!(if v { "1" } else { "0" }));
+  }
+  for (key, v) in &doc.extra {
+    object.insert(key.clone(), json!(v));
+  }
+  object.insert("version".into(), json!("2"));
+
+  Ok(serde_json::to_string_pretty(&Value::Object(object)).expect("should be serialized"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::profile::fixture_profiles;
+  use crate::step::advanced_steps;
+
+  const JSON: &str = r#"{
+  "title": "Blooming espresso",
+  "author": "Decent",
+  "notes": "Fill, bloom, then extract.",
+  "beverage_type": "espresso",
+  "steps": [
+    {
+      "name": "fill",
+      "temperature": "92.00",
+      "sensor": "coffee",
+      "pump": "flow",
+      "transition": "fast",
+      "pressure": "1",
+      "flow": 8,
+      "seconds": "25",
+      "volume": "0",
+      "exit": {"type": "pressure", "condition": "over", "value": "4"}
+    },
+    {
+      "name": "extract",
+      "temperature": "92.00",
+      "sensor": "coffee",
+      "pump": "flow",
+      "transition": "smooth",
+      "flow": "2.2",
+      "seconds": "60",
+      "limiter": {"value": "9", "range": "0.6"},
+      "popup": "Stir"
+    }
+  ],
+  "target_weight": "45",
+  "legacy_profile_type": "settings_2c",
+  "type": "advanced",
+  "lang": "en",
+  "hidden": "0",
+  "version": "2"
+}"#;
+
+  #[test]
+  fn test_is_json() {
+    assert!(is_json(JSON));
+    assert!(!is_json("\n { }"));
+    assert!(!is_json("{flow 8}"));
+  }
+
+  #[test]
+  fn test_read() {
+    let doc = read(JSON).unwrap();
+    assert_eq!(doc.title.as_deref(), Some("Blooming espresso"));
+    assert_eq!(doc.target_weight, Some(45.0));
+    assert_eq!(doc.profile_type, Some(ProfileType::Advanced));
+    assert_eq!(doc.language.as_deref(), Some("en"));
+    let steps = doc.steps.unwrap();
+    assert_eq!(
+      steps[0],
+      Step(vec![
+        Prop::Name("fill".into()),
+        Prop::Temperature(92.0),
+        Prop::Sensor(SensorType::Coffee),
+        Prop::Pump(PumpType::Flow),
+        Prop::Transition(TransitionType::Fast),
+        Prop::Pressure(1.0),
+        Prop::Flow(8.0),
+        Prop::Seconds(25.0),
+        Prop::Volume(0.0),
+        Prop::ExitIf(true),
+        Prop::ExitType(ExitType::PressureOver),
+        Prop::ExitPressureOver(4.0),
+      ])
+    );
+    let extract = AdvancedStep::try_from(&steps[1]).unwrap();
+    assert_eq!(extract.max_flow_or_pressure, 9.0);
+    assert!(!extract.exit_if);
+    assert_eq!(extract.unknown, vec![("popup".into(), "Stir".into())]);
+  }
+
+  #[test]
+  fn test_read_errors() {
+    assert_eq!(
+      read(r#"{"steps": [{"pump": "water"}]}"#),
+      Err(error(Some(0), "pump", "`flow` or `pressure`"))
+    );
+    assert_eq!(
+      read(r#"{"steps": [{"seconds": "4"}, {"flow": "-2"}]}"#),
+      Err(error(Some(1), "flow", "a value of 0 or more"))
+    );
+    let position = |text| read(text).unwrap_err().position.unwrap();
+    assert_eq!(
+      position("{\"title\": }"),
+      Position {
+        offset: 10,
+        line: 1,
+        column: 11
+      }
+    );
+    assert_eq!(position("{\"title\": \"x\",\n").line, 2);
+    assert_eq!(position("{\"title\": \"x\",\n").column, 1);
+    // columns count characters, serde_json counts bytes
+    assert_eq!(position("{\"title\": \"é\" x}").column, 15);
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let doc = read(JSON).unwrap();
+    let again = read(&write(&doc).unwrap()).unwrap();
+    assert_eq!(again.title, doc.title);
+    assert_eq!(again.target_weight, doc.target_weight);
+    assert_eq!(
+      again
+        .shot_steps()
+        .iter()
+        .map(|s| AdvancedStep::try_from(s).unwrap())
+        .collect::<Vec<_>>(),
+      doc
+        .shot_steps()
+        .iter()
+        .map(|s| AdvancedStep::try_from(s).unwrap())
+        .collect::<Vec<_>>()
+    );
+  }
+
+  #[test]
+  fn test_write_errors() {
+    let mut doc = ProfileDocument::default();
+    doc.steps = Some(vec![Step(vec![Prop::Seconds(4.0), Prop::Seconds(5.0)])]);
+    assert_eq!(
+      write(&doc),
+      Err(error(
+        Some(0),
+        "seconds",
+        "each property only once per step"
+      ))
+    );
+  }
+
+  #[test]
+  fn test_round_trip_presets() {
+    for text in fixture_profiles() {
+      let doc = ProfileDocument::parse(text).unwrap();
+      let again = read(&write(&doc).unwrap()).unwrap();
+      assert!(doc.title.is_some());
+      assert_eq!(again.title, doc.title);
+      assert!(!doc.shot_steps().is_empty());
+      // JSON keeps only the exit a step acts on
+      let shot = |doc: &ProfileDocument| {
+        advanced_steps(&doc.shot_steps())
+          .iter()
+          .map(|step| (step.pump, step.pressure, step.flow, step.exit()))
+          .collect::<Vec<_>>()
+      };
+      assert_eq!(shot(&again), shot(&doc), "{:?}", doc.title);
+    }
+  }
+}
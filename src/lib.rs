@@ -4,35 +4,110 @@
 #![allow(clippy::wildcard_imports)]
 
 mod axis;
+mod browser;
+mod classify;
+mod convert;
+mod diff;
+mod document;
+mod estimate;
+mod json;
+mod lint;
 mod msg;
 mod parser;
 mod profile;
 mod scale;
+mod serializer;
+mod step;
+mod storage;
+mod summary;
+mod timeline;
 mod utils;
 mod view;
 
+use std::collections::HashMap;
+
 use seed::prelude::*;
 use seed::*;
 
-use msg::Msg;
-use parser::{steps, Step};
-use profile::PROFILES;
-use view::view_svg;
+use browser::{beverage_types, duration, search, Filter};
+use classify::{classify, Style};
+use diff::{diff, Change, StepDiff};
+use document::{ProfileDocument, ProfileType};
+use lint::{lint, Finding, Severity};
+use msg::{FilterField, Format, Meta, Msg, PuckField};
+use parser::{number, parse_recover, Diagnostic, PumpType, Step};
+use profile::{simulate, PuckModel, PROFILES};
+use serializer::steps;
+use step::advanced_steps;
+use storage::Saved;
+use summary::{summary, Summary};
+use timeline::ThermalLag;
+use utils::{console_log, download, files, read_text};
+use view::{view_simulation, view_svg};
 
-use crate::profile::Preset;
+use crate::profile::{Preset, Source};
 
 // ------ ------
 //     Init
 // ------ ------
 
 // `init` describes what should happen when your app started.
-fn init(_: Url, _: &mut impl Orders<Msg>) -> Model {
-  Model {
+fn init(_: Url, orders: &mut impl Orders<Msg>) -> Model {
+  let mut model = Model {
     text: "".into(),
     steps: vec![],
-    error: false,
+    errors: vec![],
+    document: ProfileDocument::default(),
     selected: None,
+    user_presets: HashMap::default(),
+    filter: Filter::default(),
+    baseline: None,
+    puck: PuckModel::default(),
+    thermal_lag: None,
+    import_errors: vec![],
+  };
+  if let Some(saved) = storage::load() {
+    restore(&mut model, saved);
+    orders.send_msg(Msg::Change(model.text.clone()));
   }
+  restore_presets(&mut model, storage::load_presets());
+  model
+}
+
+fn restore(model: &mut Model, saved: Saved) {
+  model.document = ProfileDocument::parse(&saved.document).unwrap_or_default();
+  model.selected = saved.selected;
+  model.text = saved.text;
+}
+
+fn restore_presets(model: &mut Model, user_profiles: Vec<(String, String)>) {
+  for (key, text) in user_profiles {
+    match Preset::load(&text, Source::User) {
+      Ok(preset) => {
+        model.user_presets.insert(key, preset);
+      }
+      Err(error) => console_log(format!("{}: {}", key, error)),
+    }
+  }
+}
+
+fn save(model: &Model) {
+  storage::save(Saved {
+    text: model.text.clone(),
+    document: model.document.write(),
+    selected: model.selected.clone(),
+    ..Saved::default()
+  });
+}
+
+fn save_presets(model: &Model) {
+  storage::save_presets(
+    model
+      .user_presets
+      .iter()
+      .map(|(key, preset)| (key.clone(), preset.document.write()))
+      .collect(),
+  );
 }
 
 // ------ ------
@@ -43,8 +118,33 @@ fn init(_: Url, _: &mut impl Orders<Msg>) -> Model {
 struct Model {
   text: String,
   steps: Vec<Step>,
-  error: bool,
+  errors: Vec<Diagnostic>,
+  document: ProfileDocument,
   selected: Option<String>,
+  // keyed like `PROFILES`, with a `user/` prefix
+  user_presets: HashMap<String, Preset>,
+  filter: Filter,
+  // a snapshot of the steps to compare the editor against
+  baseline: Option<Baseline>,
+  puck: PuckModel,
+  // estimate the basket temperature behind water-sensor steps
+  thermal_lag: Option<ThermalLag>,
+  // files from the last pick or drop that couldn't be imported
+  import_errors: Vec<String>,
+}
+
+struct Baseline {
+  title: String,
+  steps: Vec<Step>,
+}
+
+// The profile being edited: the metadata of the last loaded profile with the
+// steps from the textarea.
+fn current_document(model: &Model) -> ProfileDocument {
+  let mut document = model.document.clone();
+  document.profile_type = Some(ProfileType::Advanced);
+  document.steps = Some(model.steps.clone());
+  document
 }
 
 // ------ ------
@@ -53,23 +153,170 @@ struct Model {
 
 // `update` describes how to handle each `Msg`.
 fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
+  // only what a message changes is stored again
+  let (editor, presets) = match &msg {
+    Msg::Change(_) | Msg::Select(_) | Msg::Edit(..) => (true, false),
+    Msg::Loaded(..) => (false, true),
+    _ => (false, false),
+  };
   match msg {
     Msg::Change(text) => {
       model.text = text.clone();
-      match steps(text.as_bytes()) {
-        Ok((_, steps)) => {
-          model.steps = steps;
-          model.error = false;
+      if json::is_json(&text) {
+        match json::read(&text) {
+          Ok(document) => {
+            model.steps = document.shot_steps();
+            model.errors = vec![];
+            model.document = document;
+          }
+          Err(error) => {
+            model.steps = vec![];
+            model.errors = vec![error];
+          }
         }
-        _ => model.error = true,
+      } else {
+        let (steps, errors) = parse_recover(&text);
+        model.steps = steps;
+        model.errors = errors;
       }
     }
     Msg::Select(file_name) => {
       model.selected = Some(file_name.clone());
 
-      let data = PROFILES.get(&file_name).expect("should exist").data.clone();
-      orders.send_msg(Msg::Change(data));
+      let preset = model
+        .user_presets
+        .get(&file_name)
+        .or_else(|| PROFILES.get(&file_name))
+        .expect("should exist");
+      model.document = preset.document.clone();
+      orders.send_msg(Msg::Change(preset.data.clone()));
+    }
+    // the steps that failed to parse would be missing from the export
+    Msg::Convert(_) | Msg::Download if !model.errors.is_empty() => (),
+    Msg::Convert(format) => {
+      let text = match format {
+        Format::Tcl => Ok(format!("{}\n", steps(&model.steps))),
+        Format::Json => json::write(&current_document(model)),
+      };
+      match text {
+        Ok(text) => {
+          orders.send_msg(Msg::Change(text));
+        }
+        Err(error) => console_log(format!("not converted: {}", error)),
+      }
+    }
+    Msg::Edit(meta, value) => {
+      let text = if value.is_empty() { None } else { Some(value) };
+      let document = &mut model.document;
+      match meta {
+        Meta::Title => document.title = text,
+        Meta::Author => document.author = text,
+        Meta::Notes => document.notes = text,
+        Meta::TargetWeight => match text.as_deref().map(number) {
+          // keep the last weight while a number is half typed
+          Some(None) => (),
+          weight => document.target_weight = weight.flatten(),
+        },
+        Meta::BeverageType => document.beverage_type = text,
+      }
+    }
+    Msg::Download => {
+      let document = current_document(model);
+      if let Err(error) = download(&document.file_name(), &document.write()) {
+        console_log(format!("download failed: {:?}", error));
+      }
+    }
+    Msg::Open(list) => {
+      model.import_errors.clear();
+      for file in list {
+        let name = file.name();
+        let lower = name.to_lowercase();
+        if !lower.ends_with(".tcl") && !lower.ends_with(".json") {
+          model
+            .import_errors
+            .push(format!("{}: not a .tcl or .json profile", name));
+          continue;
+        }
+        orders.perform_cmd(async move { Msg::Loaded(name, read_text(file).await) });
+      }
+    }
+    Msg::Loaded(name, text) => {
+      let loaded = text
+        .ok_or_else(|| "can't be read".to_string())
+        .and_then(|text| Preset::load(&text, Source::User));
+      match loaded {
+        Ok(mut preset) => {
+          // untitled files keep their name, also across reloads
+          if preset.title.is_empty() {
+            preset.title = name.clone();
+            preset.document.title = Some(name.clone());
+          }
+          model.user_presets.insert(format!("user/{}", name), preset);
+        }
+        Err(error) => model.import_errors.push(format!("{}: {}", name, error)),
+      }
+    }
+    Msg::SetBaseline => {
+      model.baseline = Some(Baseline {
+        title: model.document.title.clone().unwrap_or_default(),
+        steps: model.steps.clone(),
+      });
+    }
+    Msg::ClearBaseline => model.baseline = None,
+    Msg::ThermalLag(enabled) => {
+      model.thermal_lag = if enabled {
+        Some(ThermalLag::default())
+      } else {
+        None
+      }
     }
+    Msg::Puck(field, value) => {
+      // a puck without resistance would let any pressure through
+      let resistance = matches!(
+        field,
+        PuckField::InitialResistance | PuckField::FinalResistance
+      );
+      let valid = |v: &f64| if resistance { *v > 0. } else { *v >= 0. };
+      if let Some(v) = number(&value).filter(valid) {
+        let puck = &mut model.puck;
+        match field {
+          PuckField::Headspace => puck.headspace = v,
+          PuckField::Dose => puck.dose = v,
+          PuckField::Absorption => puck.absorption = v,
+          PuckField::InitialResistance => puck.initial_resistance = v,
+          PuckField::FinalResistance => puck.final_resistance = v,
+          PuckField::ErosionTime => puck.erosion_time = v,
+        }
+      }
+    }
+    Msg::Filter(field, value) => {
+      let filter = &mut model.filter;
+      let text = if value.is_empty() { None } else { Some(value) };
+      let count = |text: Option<String>| text.and_then(|v| v.parse().ok());
+      let seconds = |text: Option<String>| text.as_deref().and_then(number);
+      match field {
+        FilterField::Query => filter.query = text.unwrap_or_default(),
+        FilterField::BeverageType => filter.beverage_type = text,
+        FilterField::Pump => {
+          filter.pump = match text.as_deref() {
+            Some("pressure") => Some(PumpType::Pressure),
+            Some("flow") => Some(PumpType::Flow),
+            _ => None,
+          }
+        }
+        FilterField::Style => filter.style = text.as_deref().and_then(Style::parse),
+        FilterField::MinSteps => filter.min_steps = count(text),
+        FilterField::MaxSteps => filter.max_steps = count(text),
+        FilterField::MinSeconds => filter.min_seconds = seconds(text),
+        FilterField::MaxSeconds => filter.max_seconds = seconds(text),
+      }
+    }
+  }
+  if editor {
+    save(model);
+  }
+  if presets {
+    save_presets(model);
   }
 }
 
@@ -79,21 +326,75 @@ fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
 
 // `view` describes what to display.
 fn view(model: &Model) -> Node<Msg> {
+  let steps = advanced_steps(&model.steps);
+  let baseline = model.baseline.as_ref().map(|baseline| {
+    let rows = diff(&baseline.steps, &model.steps);
+    (&baseline.steps, advanced_steps(&baseline.steps), rows)
+  });
+  let highlight = baseline.as_ref().map_or(vec![], |(.., rows)| {
+    rows
+      .iter()
+      .filter(|row| row.change != Change::Same)
+      .filter_map(|row| row.after)
+      .collect()
+  });
   div![
     style! {
         St::Display => "flex",
         St::FlexDirection => "row",
     },
     div![
-      div![view_svg(&model.steps)],
-      div![view_syntax_error(model.error)],
+      div![
+        style! { St::Display => "flex", St::AlignItems => "flex-start" },
+        div![view_svg(
+          &steps,
+          baseline.as_ref().map(|(_, steps, _)| &steps[..]),
+          &highlight,
+          &model.puck,
+          model.document.target_weight,
+          model.thermal_lag.as_ref(),
+        )],
+        view_summary(&summary(&steps, &model.puck)),
+      ],
+      div![label![
+        input![
+          attrs! {
+            At::Type => "checkbox",
+            At::Checked => model.thermal_lag.is_some().as_at_value(),
+          },
+          ev(Ev::Change, |event| {
+            let checked = event
+              .target()
+              .and_then(|target| target.dyn_into::<web_sys::HtmlInputElement>().ok())
+              .map_or(false, |input| input.checked());
+            Msg::ThermalLag(checked)
+          }),
+        ],
+        " estimate basket temperature behind water-sensor steps",
+      ]],
+      div![view_baseline(model)],
+      div![
+        h4!["Simulated shot"],
+        view_simulation(&simulate(&steps, &model.puck))
+      ],
+      div![view_puck(&model.puck)],
+      baseline
+        .as_ref()
+        .map(|(before, _, rows)| view_diff(rows, before, &model.steps)),
+      div![view_findings(&lint(&steps))],
       div![model.steps.iter().map(|step| view_step(step))],
       hr![],
       div![&model.text, style! {St::WhiteSpace => "pre-wrap"},],
       style! {St::Flex => "1 1 0px",},
     ],
     div![
-      div![view_profile_selector()],
+      div![
+        view_file_picker(&model.import_errors),
+        view_convert_buttons(model.errors.is_empty())
+      ],
+      div![view_preset_browser(model)],
+      div![view_metadata(&model.document, model.errors.is_empty())],
+      div![view_syntax_errors(&model.text, &model.errors)],
       textarea![
         attrs! {
           At::Value => model.text.clone(),
@@ -108,24 +409,243 @@ fn view(model: &Model) -> Node<Msg> {
           St::Flex => "1 1 0px",
           St::MinHeight => "400px",
       },
+      // profile files can be dropped anywhere on the editor side
+      drag_ev(Ev::DragOver, |event| event.prevent_default()),
+      drag_ev(Ev::Drop, |event| {
+        event.prevent_default();
+        Msg::Open(files(event.data_transfer().and_then(|data| data.files())))
+      }),
     ],
   ]
 }
 
-fn view_syntax_error(error: bool) -> Vec<Node<Msg>> {
+fn view_syntax_errors(text: &str, errors: &[Diagnostic]) -> Vec<Node<Msg>> {
   let mut children = vec![];
-  if error {
-    children.push(span![
-      "Syntax Error",
+  for diagnostic in errors {
+    children.push(div![
+      format!("Syntax Error: {}", diagnostic),
       style! {
         St::Color => "red",
         St::FontWeight => "bold",
       }
     ]);
+    let position = match diagnostic.position {
+      Some(position) => position,
+      None => continue,
+    };
+    let line = text.lines().nth(position.line - 1).unwrap_or_default();
+    children.push(pre![
+      format!("{}\n{}^", line, " ".repeat(position.column - 1)),
+      style! {
+        St::Color => "red",
+        St::Margin => "0",
+        St::OverflowX => "auto",
+      }
+    ]);
   }
   children
 }
 
+fn view_findings(findings: &[Finding]) -> Vec<Node<Msg>> {
+  findings
+    .iter()
+    .map(|finding| {
+      let color = match finding.severity {
+        Severity::Info => "gray",
+        Severity::Warning => "darkorange",
+        Severity::Error => "red",
+      };
+      div![
+        attrs! { At::Title => format!("{}: {}", finding.rule.code(), finding.rule.description()) },
+        finding.to_string(),
+        style! { St::Color => color },
+      ]
+    })
+    .collect()
+}
+
+fn view_summary(summary: &Summary) -> Node<Msg> {
+  let seconds = |v: f64| format!("{:.1} s", v);
+  let exits = summary
+    .exits
+    .iter()
+    .map(|reason| reason.to_string())
+    .collect::<Vec<_>>();
+  let rows = vec![
+    ("Total time", seconds(summary.total_time)),
+    (
+      "Preinfusion",
+      summary.preinfusion.map_or("-".into(), seconds),
+    ),
+    ("Peak pressure", format!("{:.1} bar", summary.peak_pressure)),
+    ("Peak flow", format!("{:.1} ml/s", summary.peak_flow)),
+    (
+      "Temperature",
+      summary
+        .temperature
+        .map_or("-".into(), |(min, max)| format!("{:.1}-{:.1} °C", min, max)),
+    ),
+    (
+      "Steps",
+      format!(
+        "{} pressure, {} flow",
+        summary.pressure_steps, summary.flow_steps
+      ),
+    ),
+    (
+      "Exits",
+      if exits.is_empty() {
+        "-".into()
+      } else {
+        exits.join(", ")
+      },
+    ),
+    ("Water", format!("{:.0} ml", summary.volume)),
+    (
+      "Style",
+      if summary.styles.is_empty() {
+        "-".into()
+      } else {
+        summary
+          .styles
+          .iter()
+          .map(|style| style.to_string())
+          .collect::<Vec<_>>()
+          .join(", ")
+      },
+    ),
+  ];
+  table![
+    style! {
+      St::Border => "1px solid lightgray",
+      St::Padding => "4px",
+      St::FontSize => "small",
+      St::WhiteSpace => "nowrap",
+    },
+    rows
+      .into_iter()
+      .map(|(label, value)| { tr![th![label, style! { St::TextAlign => "left" }], td![value],] }),
+  ]
+}
+
+fn view_puck(puck: &PuckModel) -> Vec<Node<Msg>> {
+  let field = |label: &str, value: f64, field: PuckField| {
+    label![
+      format!("{} ", label),
+      input![
+        attrs! {
+          At::Type => "number",
+          At::Min => "0",
+          At::Step => "0.1",
+          At::Value => value,
+        },
+        style! { St::Width => "5em" },
+        input_ev(Ev::Input, move |value| Msg::Puck(field, value)),
+      ],
+      " ",
+    ]
+  };
+  vec![
+    field("headspace ml", puck.headspace, PuckField::Headspace),
+    field("dose g", puck.dose, PuckField::Dose),
+    field("absorbed ml/g", puck.absorption, PuckField::Absorption),
+    field(
+      "resistance from",
+      puck.initial_resistance,
+      PuckField::InitialResistance,
+    ),
+    field("to", puck.final_resistance, PuckField::FinalResistance),
+    field("erosion s", puck.erosion_time, PuckField::ErosionTime),
+  ]
+}
+
+fn view_baseline(model: &Model) -> Vec<Node<Msg>> {
+  let mut children = vec![button![
+    "Compare against these steps",
+    ev(Ev::Click, |_| Msg::SetBaseline)
+  ]];
+  if let Some(baseline) = &model.baseline {
+    children.push(span![format!(
+      " comparing with {} ({} steps) ",
+      if baseline.title.is_empty() {
+        "snapshot"
+      } else {
+        &baseline.title
+      },
+      baseline.steps.len()
+    )]);
+    children.push(button!["Clear", ev(Ev::Click, |_| Msg::ClearBaseline)]);
+  }
+  children
+}
+
+fn step_title(steps: &[Step], index: Option<usize>) -> String {
+  let index = match index {
+    Some(index) => index,
+    None => return "".into(),
+  };
+  let name = advanced_steps(&steps[index..=index])
+    .pop()
+    .map(|step| step.name)
+    .unwrap_or_default();
+  format!("{}. {}", index + 1, name)
+}
+
+// Side by side: the baseline step on the left, the edited one on the right,
+// and a line per property that differs.
+fn view_diff(rows: &[StepDiff], before: &[Step], after: &[Step]) -> Node<Msg> {
+  let cell = || style! { St::Border => "1px solid lightgray", St::Padding => "2px 4px" };
+  table![
+    style! { St::BorderCollapse => "collapse", St::Width => "100%" },
+    tr![th!["baseline"], th!["edited"], th!["change"]],
+    rows.iter().map(|row| {
+      let color = match row.change {
+        Change::Added => "honeydew",
+        Change::Removed => "mistyrose",
+        Change::Moved => "lavender",
+        Change::Changed => "lightyellow",
+        Change::Same => "white",
+      };
+      let label = match row.change {
+        Change::Added => "added",
+        Change::Removed => "removed",
+        Change::Moved => "moved",
+        Change::Changed => "changed",
+        Change::Same => "",
+      };
+      let mut lines = vec![tr![
+        style! { St::BackgroundColor => color },
+        td![cell(), strong![step_title(before, row.before)]],
+        td![cell(), strong![step_title(after, row.after)]],
+        td![cell(), label],
+      ]];
+      if !row.props.is_empty() {
+        lines.push(tr![
+          style! { St::BackgroundColor => color },
+          td![
+            cell(),
+            row.props.iter().map(|prop| div![format!(
+              "{} {}",
+              prop.name,
+              prop.before.as_deref().unwrap_or("-")
+            )])
+          ],
+          td![
+            cell(),
+            row.props.iter().map(|prop| div![format!(
+              "{} {}",
+              prop.name,
+              prop.after.as_deref().unwrap_or("-")
+            )])
+          ],
+          td![cell()],
+        ]);
+      }
+      lines
+    }),
+  ]
+}
+
 fn view_step(step: &Step) -> Node<Msg> {
   div![
     step.0.iter().map(|prop| div![format!("{:?}", prop),]),
@@ -133,19 +653,224 @@ fn view_step(step: &Step) -> Node<Msg> {
   ]
 }
 
-fn view_profile_selector() -> Node<Msg> {
-  let mut presets = PROFILES
+fn view_preset_browser(model: &Model) -> Vec<Node<Msg>> {
+  let presets = || PROFILES.iter().chain(model.user_presets.iter());
+  let filter = &model.filter;
+  let number_input = |placeholder: &str, field: FilterField| {
+    input![
+      attrs! {
+        At::Type => "number",
+        At::Placeholder => placeholder,
+        At::Min => "0",
+      },
+      style! { St::Width => "6em" },
+      input_ev(Ev::Input, move |value| Msg::Filter(field, value)),
+    ]
+  };
+
+  let results = search(presets(), filter);
+  vec![
+    div![
+      input![
+        attrs! {
+          At::Type => "search",
+          At::Placeholder => "search title, author and notes",
+          At::Value => &filter.query,
+        },
+        style! { St::Width => "100%" },
+        input_ev(Ev::Input, |value| Msg::Filter(FilterField::Query, value)),
+      ],
+      select![
+        option![attrs! { At::Value => "" }, "any beverage"],
+        beverage_types(presets().map(|(_, preset)| preset))
+          .into_iter()
+          .map(|v| option![attrs! { At::Value => &v }, &v]),
+        input_ev(Ev::Change, |value| Msg::Filter(
+          FilterField::BeverageType,
+          value
+        )),
+      ],
+      select![
+        option![attrs! { At::Value => "" }, "any pump"],
+        option![attrs! { At::Value => "pressure" }, "uses pressure"],
+        option![attrs! { At::Value => "flow" }, "uses flow"],
+        input_ev(Ev::Change, |value| Msg::Filter(FilterField::Pump, value)),
+      ],
+      select![
+        option![attrs! { At::Value => "" }, "any style"],
+        Style::ALL
+          .iter()
+          .map(|style| option![attrs! { At::Value => style.as_str() }, style.to_string()]),
+        input_ev(Ev::Change, |value| Msg::Filter(FilterField::Style, value)),
+      ],
+      div![
+        "steps ",
+        number_input("min", FilterField::MinSteps),
+        " - ",
+        number_input("max", FilterField::MaxSteps),
+        " seconds ",
+        number_input("min", FilterField::MinSeconds),
+        " - ",
+        number_input("max", FilterField::MaxSeconds),
+      ],
+    ],
+    div![format!("{} profiles", results.len())],
+    div![
+      results.into_iter().map(|(key, preset)| view_preset(
+        key,
+        preset,
+        model.selected.as_ref() == Some(key)
+      )),
+      style! {
+        St::MaxHeight => "300px",
+        St::OverflowY => "auto",
+        St::Border => "1px solid gray",
+      },
+    ],
+  ]
+}
+
+fn view_preset(key: &str, preset: &Preset, selected: bool) -> Node<Msg> {
+  let key = key.to_string();
+  let author = preset.document.author.as_deref().unwrap_or_default();
+  let styles = classify(&preset.steps)
     .iter()
-    .map(|(file_name, preset)| (file_name.clone(), preset.clone()))
-    .collect::<Vec<(String, Preset)>>();
-  presets.sort_by(|(_, p1), (_, p2)| p1.title.cmp(&p2.title));
+    .map(|style| style.to_string())
+    .collect::<Vec<_>>();
+  div![
+    div![
+      strong![&preset.title],
+      format!(" [{}]", preset.source),
+      if styles.is_empty() {
+        empty![]
+      } else {
+        span![
+          format!(" {}", styles.join(", ")),
+          style! { St::Color => "darkgreen", St::FontSize => "small" },
+        ]
+      },
+    ],
+    div![
+      format!(
+        "{} steps, {} s{}",
+        preset.steps.len(),
+        duration(preset),
+        if author.is_empty() {
+          "".into()
+        } else {
+          format!(", by {}", author)
+        }
+      ),
+      style! { St::Color => "gray" },
+    ],
+    div![
+      &preset.notes,
+      style! {
+        St::FontSize => "small",
+        St::WhiteSpace => "pre-wrap",
+        St::MaxHeight => "4.5em",
+        St::Overflow => "hidden",
+      },
+    ],
+    style! {
+      St::Padding => "4px",
+      St::Cursor => "pointer",
+      St::BorderBottom => "1px solid lightgray",
+      St::BackgroundColor => if selected { "lightyellow" } else { "white" },
+    },
+    ev(Ev::Click, move |_| Msg::Select(key)),
+  ]
+}
 
-  select![
-    option!["--- select profile ---"],
-    presets
-      .iter()
-      .map(|(file_name, preset)| option![attrs! { At::Value => file_name }, preset.title.as_str()]),
-    input_ev(Ev::Change, Msg::Select)
+fn view_file_picker(errors: &[String]) -> Vec<Node<Msg>> {
+  let mut children = vec![input![
+    attrs! {
+      At::Type => "file",
+      At::Multiple => AtValue::None,
+      At::Accept => ".tcl,.json",
+    },
+    ev(Ev::Change, |event| {
+      let input = event
+        .target()
+        .and_then(|target| target.dyn_into::<web_sys::HtmlInputElement>().ok());
+      Msg::Open(files(input.and_then(|input| input.files())))
+    }),
+  ]];
+  for error in errors {
+    children.push(span![
+      error,
+      style! {
+        St::Color => "red",
+        St::MarginRight => "8px",
+      }
+    ]);
+  }
+  children
+}
+
+// Exports are held back while the editor has syntax errors.
+fn view_export_button(
+  label: &str,
+  exportable: bool,
+  handler: impl FnOnce(web_sys::Event) -> Msg + Clone + 'static,
+) -> Node<Msg> {
+  button![
+    label,
+    attrs! {
+      At::Disabled => (!exportable).as_at_value(),
+      At::Title => if exportable {
+        AtValue::Ignored
+      } else {
+        AtValue::Some("fix the syntax errors first".into())
+      },
+    },
+    ev(Ev::Click, handler),
+  ]
+}
+
+fn view_convert_buttons(exportable: bool) -> Vec<Node<Msg>> {
+  vec![
+    view_export_button("Tcl", exportable, |_| Msg::Convert(Format::Tcl)),
+    view_export_button("JSON", exportable, |_| Msg::Convert(Format::Json)),
+  ]
+}
+
+fn view_metadata(document: &ProfileDocument, exportable: bool) -> Vec<Node<Msg>> {
+  let text = |value: &Option<String>| value.clone().unwrap_or_default();
+  let field = |label: &str, value: String, meta: Meta| {
+    div![label![
+      label,
+      input![
+        attrs! { At::Value => value },
+        input_ev(Ev::Input, move |value| Msg::Edit(meta, value)),
+      ],
+    ]]
+  };
+  vec![
+    field("Title", text(&document.title), Meta::Title),
+    field("Author", text(&document.author), Meta::Author),
+    field(
+      "Beverage type",
+      text(&document.beverage_type),
+      Meta::BeverageType,
+    ),
+    field(
+      "Target weight",
+      document
+        .target_weight
+        .map(|v| v.to_string())
+        .unwrap_or_default(),
+      Meta::TargetWeight,
+    ),
+    div![label![
+      "Notes",
+      textarea![
+        attrs! { At::Value => text(&document.notes) },
+        style! { St::Width => "100%" },
+        input_ev(Ev::Input, |value| Msg::Edit(Meta::Notes, value)),
+      ],
+    ]],
+    view_export_button("Download .tcl", exportable, |_| Msg::Download),
   ]
 }
 
@@ -0,0 +1,321 @@
+use std::fmt;
+
+use crate::parser::{ExitType, PumpType, TransitionType};
+use crate::step::AdvancedStep;
+
+// The DE1 firmware holds at most this many shot frames.
+const MAX_STEPS: usize = 20;
+const TEMPERATURE_RANGE: (f64, f64) = (80., 105.);
+// Above these a limiter can't be meant for the series it would cap: the pump
+// can't push more flow, and the group can't build more pressure.
+const MAX_FLOW: f64 = 10.;
+const MAX_PRESSURE: f64 = 12.;
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Severity {
+  Info,
+  Warning,
+  Error,
+}
+
+impl fmt::Display for Severity {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let label = match self {
+      Severity::Info => "info",
+      Severity::Warning => "warning",
+      Severity::Error => "error",
+    };
+    write!(f, "{}", label)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+  UnreachableExit,
+  ZeroSeconds,
+  TemperatureRange,
+  TooManySteps,
+  LimiterOnWrongPump,
+  ExitTypeMissing,
+}
+
+impl Rule {
+  pub fn code(&self) -> &'static str {
+    match self {
+      Rule::UnreachableExit => "unreachable-exit",
+      Rule::ZeroSeconds => "zero-seconds",
+      Rule::TemperatureRange => "temperature-range",
+      Rule::TooManySteps => "too-many-steps",
+      Rule::LimiterOnWrongPump => "limiter-on-wrong-pump",
+      Rule::ExitTypeMissing => "exit-type-missing",
+    }
+  }
+
+  pub fn severity(&self) -> Severity {
+    match self {
+      Rule::UnreachableExit => Severity::Warning,
+      Rule::ZeroSeconds => Severity::Info,
+      Rule::TemperatureRange => Severity::Warning,
+      Rule::TooManySteps => Severity::Error,
+      Rule::LimiterOnWrongPump => Severity::Warning,
+      Rule::ExitTypeMissing => Severity::Error,
+    }
+  }
+
+  pub fn description(&self) -> &'static str {
+    match self {
+      Rule::UnreachableExit => "an exit threshold the step's own target or limiter can never reach",
+      Rule::ZeroSeconds => "a step that runs for zero seconds",
+      Rule::TemperatureRange => "a temperature outside 80-105 °C",
+      Rule::TooManySteps => "more steps than the firmware's 20 frames",
+      Rule::LimiterOnWrongPump => "a limiter value that only makes sense on the other pump type",
+      Rule::ExitTypeMissing => "`exit_if 1` without an `exit_type`",
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+  pub rule: Rule,
+  pub severity: Severity,
+  pub step: Option<usize>,
+  pub message: String,
+}
+
+impl Finding {
+  fn new(rule: Rule, step: Option<usize>, message: String) -> Self {
+    Self {
+      rule,
+      severity: rule.severity(),
+      step,
+      message,
+    }
+  }
+}
+
+impl fmt::Display for Finding {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.step {
+      Some(step) => write!(f, "{} (step {}): {}", self.severity, step + 1, self.message),
+      None => write!(f, "{}: {}", self.severity, self.message),
+    }
+  }
+}
+
+// The highest value the step can reach on the series an exit watches: the
+// target when the pump controls that series, otherwise the limiter if one is
+// set. A smooth step also passes through where the previous step ended.
+fn reachable(step: &AdvancedStep, prev: Option<&AdvancedStep>, pump: PumpType) -> Option<f64> {
+  let limit = if step.pump == pump {
+    step.target()
+  } else if step.max_flow_or_pressure > 0. {
+    step.max_flow_or_pressure
+  } else {
+    return None;
+  };
+
+  match (step.transition, prev) {
+    (TransitionType::Smooth, Some(prev)) if prev.pump == pump => Some(limit.max(prev.target())),
+    _ => Some(limit),
+  }
+}
+
+fn lint_step(index: usize, step: &AdvancedStep, prev: Option<&AdvancedStep>) -> Vec<Finding> {
+  let mut findings = vec![];
+  let at = Some(index);
+
+  if let Some((exit_type, v)) = step.exit() {
+    let (pump, unit) = match exit_type {
+      ExitType::PressureOver => (PumpType::Pressure, "bar"),
+      ExitType::FlowOver => (PumpType::Flow, "ml/s"),
+      _ => (step.pump, ""),
+    };
+    let over = matches!(exit_type, ExitType::PressureOver | ExitType::FlowOver);
+    match reachable(step, prev, pump) {
+      Some(limit) if over && v > limit => findings.push(Finding::new(
+        Rule::UnreachableExit,
+        at,
+        format!(
+          "exits over {} {} but can only reach {} {}",
+          v, unit, limit, unit
+        ),
+      )),
+      _ => (),
+    }
+  }
+
+  if step.seconds == 0. {
+    findings.push(Finding::new(
+      Rule::ZeroSeconds,
+      at,
+      "runs for 0 seconds".into(),
+    ));
+  }
+
+  let (min, max) = TEMPERATURE_RANGE;
+  if step.temperature < min || max < step.temperature {
+    findings.push(Finding::new(
+      Rule::TemperatureRange,
+      at,
+      format!(
+        "temperature {} °C is outside {}-{} °C",
+        step.temperature, min, max
+      ),
+    ));
+  }
+
+  let limiter = step.max_flow_or_pressure;
+  match step.pump {
+    PumpType::Pressure if limiter > MAX_FLOW => findings.push(Finding::new(
+      Rule::LimiterOnWrongPump,
+      at,
+      format!(
+        "limiter {} caps flow on a pressure step; it looks like a pressure limit for a flow step",
+        limiter
+      ),
+    )),
+    PumpType::Flow if limiter > MAX_PRESSURE => findings.push(Finding::new(
+      Rule::LimiterOnWrongPump,
+      at,
+      format!(
+        "limiter {} caps pressure on a flow step; it looks like a flow limit for a pressure step",
+        limiter
+      ),
+    )),
+    _ => (),
+  }
+
+  if step.exit_if && step.exit_type.is_none() {
+    findings.push(Finding::new(
+      Rule::ExitTypeMissing,
+      at,
+      "`exit_if 1` is set but there is no `exit_type`".into(),
+    ));
+  }
+
+  findings
+}
+
+pub fn lint(steps: &[AdvancedStep]) -> Vec<Finding> {
+  let mut findings = vec![];
+  if steps.len() > MAX_STEPS {
+    findings.push(Finding::new(
+      Rule::TooManySteps,
+      None,
+      format!(
+        "{} steps, but the firmware only holds {}",
+        steps.len(),
+        MAX_STEPS
+      ),
+    ));
+  }
+
+  for (index, step) in steps.iter().enumerate() {
+    let prev = index.checked_sub(1).map(|i| &steps[i]);
+    findings.append(&mut lint_step(index, step, prev));
+  }
+  findings
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn step() -> AdvancedStep {
+    AdvancedStep {
+      temperature: 92.,
+      pressure: 9.,
+      seconds: 10.,
+      ..AdvancedStep::default()
+    }
+  }
+
+  fn rules(steps: &[AdvancedStep]) -> Vec<(Rule, Option<usize>)> {
+    lint(steps).iter().map(|f| (f.rule, f.step)).collect()
+  }
+
+  #[test]
+  fn test_clean() {
+    assert_eq!(rules(&[step(), step()]), vec![]);
+  }
+
+  #[test]
+  fn test_unreachable_exit() {
+    let fill = AdvancedStep {
+      pressure: 2.,
+      exit_if: true,
+      exit_type: Some(ExitType::PressureOver),
+      exit_pressure_over: 4.,
+      ..step()
+    };
+    assert_eq!(
+      rules(std::slice::from_ref(&fill)),
+      vec![(Rule::UnreachableExit, Some(0))]
+    );
+
+    // a smooth decline passes through the previous target
+    let decline = AdvancedStep {
+      transition: TransitionType::Smooth,
+      ..fill.clone()
+    };
+    assert_eq!(rules(&[step(), decline]), vec![]);
+
+    // on a flow step, pressure is capped by the limiter
+    let limited = AdvancedStep {
+      pump: PumpType::Flow,
+      flow: 2.,
+      max_flow_or_pressure: 3.,
+      ..fill.clone()
+    };
+    assert_eq!(
+      rules(std::slice::from_ref(&limited)),
+      vec![(Rule::UnreachableExit, Some(0))]
+    );
+    let unlimited = AdvancedStep {
+      max_flow_or_pressure: 0.,
+      ..limited
+    };
+    assert_eq!(rules(&[unlimited]), vec![]);
+  }
+
+  #[test]
+  fn test_step_rules() {
+    let list = vec![
+      AdvancedStep {
+        seconds: 0.,
+        ..step()
+      },
+      AdvancedStep {
+        temperature: 70.,
+        ..step()
+      },
+      AdvancedStep {
+        max_flow_or_pressure: 11.,
+        ..step()
+      },
+      AdvancedStep {
+        exit_if: true,
+        ..step()
+      },
+    ];
+    assert_eq!(
+      rules(&list),
+      vec![
+        (Rule::ZeroSeconds, Some(0)),
+        (Rule::TemperatureRange, Some(1)),
+        (Rule::LimiterOnWrongPump, Some(2)),
+        (Rule::ExitTypeMissing, Some(3)),
+      ]
+    );
+    assert_eq!(lint(&list)[3].severity, Severity::Error);
+  }
+
+  #[test]
+  fn test_too_many_steps() {
+    assert_eq!(
+      rules(&vec![step(); 21]),
+      vec![(Rule::TooManySteps, None)]
+    );
+    assert_eq!(rules(&vec![step(); 20]), vec![]);
+  }
+}
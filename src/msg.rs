@@ -1,4 +1,51 @@
+#[derive(Clone, Copy)]
+pub enum Format {
+  Tcl,
+  Json,
+}
+
+#[derive(Clone, Copy)]
+pub enum Meta {
+  Title,
+  Author,
+  Notes,
+  TargetWeight,
+  BeverageType,
+}
+
+#[derive(Clone, Copy)]
+pub enum FilterField {
+  Query,
+  BeverageType,
+  Pump,
+  Style,
+  MinSteps,
+  MaxSteps,
+  MinSeconds,
+  MaxSeconds,
+}
+
+#[derive(Clone, Copy)]
+pub enum PuckField {
+  Headspace,
+  Dose,
+  Absorption,
+  InitialResistance,
+  FinalResistance,
+  ErosionTime,
+}
+
 pub enum Msg {
   Change(String),
   Select(String),
+  Convert(Format),
+  Edit(Meta, String),
+  Download,
+  Open(Vec<web_sys::File>),
+  Loaded(String, Option<String>),
+  Filter(FilterField, String),
+  SetBaseline,
+  ClearBaseline,
+  Puck(PuckField, String),
+  ThermalLag(bool),
 }
@@ -1,23 +1,33 @@
 use std::convert::TryFrom;
+use std::fmt;
 use std::str::{self, FromStr};
 
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take_till, take_until};
+use nom::bytes::complete::{tag, take_while1};
 use nom::character::complete::{multispace0, multispace1};
 use nom::character::{
-  complete::{space1, u16},
+  complete::{char, digit0, digit1 as digit, one_of, space1},
   is_newline, is_space,
-  streaming::digit1 as digit,
 };
-use nom::combinator::{map, map_res, opt, peek, recognize};
+use nom::combinator::{map_res, opt, recognize};
+use nom::error::{Error, ErrorKind};
 use nom::multi::separated_list0;
-use nom::sequence::{delimited, tuple};
+use nom::sequence::tuple;
 use nom::IResult;
 
-fn unsigned_float(i: &[u8]) -> IResult<&[u8], f32> {
-  let float_bytes = recognize(alt((
-    delimited(digit, tag("."), opt(digit)),
-    delimited(opt(digit), tag("."), digit),
+use crate::step::AdvancedStep;
+
+// Accepts everything Tcl writes for a double: an optional sign, integer
+// and/or fraction digits and an optional exponent, e.g. `-1`, `+2`, `70000`,
+// `.5`, `8.` or `1e-3`.
+fn float(i: &[u8]) -> IResult<&[u8], f64> {
+  let float_bytes = recognize(tuple((
+    opt(one_of("+-")),
+    alt((
+      recognize(tuple((digit, opt(tuple((char('.'), digit0)))))),
+      recognize(tuple((char('.'), digit))),
+    )),
+    opt(tuple((one_of("eE"), opt(one_of("+-")), digit))),
   )));
   let float_str = map_res(float_bytes, str::from_utf8);
   map_res(float_str, FromStr::from_str)(i)
@@ -26,26 +36,52 @@ fn unsigned_float(i: &[u8]) -> IResult<&[u8], f32> {
 #[derive(Clone, Debug, PartialEq)]
 pub enum Prop {
   ExitIf(bool),
-  Flow(f32),
-  Volume(f32),
-  MaxFlowOrPressureRange(f32),
+  Flow(f64),
+  Volume(f64),
+  MaxFlowOrPressureRange(f64),
   Transition(TransitionType),
-  ExitFlowUnder(f32),
-  Temperature(f32),
+  ExitFlowUnder(f64),
+  Temperature(f64),
   Name(String),
-  Pressure(f32),
+  Pressure(f64),
   Sensor(SensorType),
   Pump(PumpType),
   ExitType(ExitType),
-  ExitFlowOver(f32),
-  ExitPressureOver(f32),
-  MaxFlowOrPressure(f32),
-  ExitPressureUnder(f32),
-  Seconds(f32),
-  Weight(f32),
+  ExitFlowOver(f64),
+  ExitPressureOver(f64),
+  MaxFlowOrPressure(f64),
+  ExitPressureUnder(f64),
+  Seconds(f64),
+  Weight(f64),
   Unknown((String, String)),
 }
 
+impl Prop {
+  pub fn name(&self) -> &str {
+    match self {
+      Prop::ExitIf(_) => "exit_if",
+      Prop::Flow(_) => "flow",
+      Prop::Volume(_) => "volume",
+      Prop::MaxFlowOrPressureRange(_) => "max_flow_or_pressure_range",
+      Prop::Transition(_) => "transition",
+      Prop::ExitFlowUnder(_) => "exit_flow_under",
+      Prop::Temperature(_) => "temperature",
+      Prop::Name(_) => "name",
+      Prop::Pressure(_) => "pressure",
+      Prop::Sensor(_) => "sensor",
+      Prop::Pump(_) => "pump",
+      Prop::ExitType(_) => "exit_type",
+      Prop::ExitFlowOver(_) => "exit_flow_over",
+      Prop::ExitPressureOver(_) => "exit_pressure_over",
+      Prop::MaxFlowOrPressure(_) => "max_flow_or_pressure",
+      Prop::ExitPressureUnder(_) => "exit_pressure_under",
+      Prop::Seconds(_) => "seconds",
+      Prop::Weight(_) => "weight",
+      Prop::Unknown((name, _)) => name,
+    }
+  }
+}
+
 #[derive(Clone, Debug)]
 pub struct ConvertError(String);
 
@@ -172,79 +208,6 @@ impl TryFrom<&[u8]> for ExitType {
 #[derive(Clone, Debug, PartialEq)]
 pub struct Step(pub Vec<Prop>);
 
-impl Step {
-  fn get(&self, prop_name: &str) -> Option<&Prop> {
-    self.0.iter().find(|prop| match prop {
-      Prop::ExitIf(_) => prop_name == "exit_if",
-      Prop::Flow(_) => prop_name == "flow",
-      Prop::Volume(_) => prop_name == "volume",
-      Prop::MaxFlowOrPressureRange(_) => prop_name == "max_flow_or_pressure_range",
-      Prop::Transition(_) => prop_name == "transition",
-      Prop::ExitFlowUnder(_) => prop_name == "exit_flow_under",
-      Prop::Temperature(_) => prop_name == "temperature",
-      Prop::Name(_) => prop_name == "name",
-      Prop::Pressure(_) => prop_name == "pressure",
-      Prop::Sensor(_) => prop_name == "sensor",
-      Prop::Pump(_) => prop_name == "pump",
-      Prop::ExitType(_) => prop_name == "exit_type",
-      Prop::ExitFlowOver(_) => prop_name == "exit_flow_over",
-      Prop::ExitPressureOver(_) => prop_name == "exit_pressure_over",
-      Prop::MaxFlowOrPressure(_) => prop_name == "max_flow_or_pressure",
-      Prop::ExitPressureUnder(_) => prop_name == "exit_pressure_under",
-      Prop::Seconds(_) => prop_name == "seconds",
-      Prop::Weight(_) => prop_name == "weight",
-      _ => false,
-    })
-  }
-
-  pub fn seconds(&self) -> f32 {
-    let prop = self.0.iter().find(|prop| match prop {
-      Prop::Seconds(_) => true,
-      _ => false,
-    });
-    match prop {
-      Some(Prop::Seconds(v)) => *v,
-      _ => panic!("not found: seconds"),
-    }
-  }
-
-  pub fn pump(&self) -> PumpType {
-    let prop = self.0.iter().find(|prop| match prop {
-      Prop::Pump(_) => true,
-      _ => false,
-    });
-    match prop {
-      Some(Prop::Pump(v)) => *v,
-      _ => panic!("not found: pump"),
-    }
-  }
-
-  pub fn transition(&self) -> TransitionType {
-    match self.get("transition") {
-      Some(Prop::Transition(v)) => *v,
-      _ => panic!("not found: transition"),
-    }
-  }
-
-  pub fn exit_flow(&self) -> Option<f32> {
-    match (self.get("exit_if"), self.get("exit_type")) {
-      (Some(Prop::ExitIf(true)), Some(Prop::ExitType(ExitType::FlowOver))) => {
-        match self.get("exit_flow_over") {
-          Some(Prop::ExitFlowOver(v)) => Some(*v),
-          _ => None,
-        }
-      }
-      (Some(Prop::ExitIf(true)), Some(Prop::ExitType(ExitType::FlowUnder))) => {
-        match self.get("exit_flow_under") {
-          Some(Prop::ExitFlowUnder(v)) => Some(*v),
-          _ => None,
-        }
-      }
-      _ => None,
-    }
-  }
-}
-
 impl ParsableEnumProp for ExitType {
   fn parse(i: &[u8]) -> IResult<&[u8], Prop> {
     let (i, (_, _, val)) = tuple((tag("exit_type"), space1, exit_type_val))(i)?;
@@ -281,31 +244,173 @@ fn bool_val(i: &[u8]) -> IResult<&[u8], bool> {
   Ok((i, v == &b"1"[..]))
 }
 
-fn number_val(i: &[u8]) -> IResult<&[u8], f32> {
-  match peek(unsigned_float)(i) {
-    Ok((i, _)) => unsigned_float(i),
-    _ => map(u16, |v| v as f32)(i),
+// Values a double can't hold, too large or so small they would lose digits
+// or become 0, are reported rather than rounded.
+fn number_val(i: &[u8]) -> IResult<&[u8], f64> {
+  let (rest, v) = float(i)?;
+  let mantissa = i[..i.len() - rest.len()]
+    .split(|c| *c == b'e' || *c == b'E')
+    .next()
+    .unwrap_or_default();
+  let nonzero = mantissa.iter().any(|c| (b'1'..=b'9').contains(c));
+  if !v.is_finite() || (nonzero && !v.is_normal()) {
+    return Err(nom::Err::Error(Error::new(i, ErrorKind::TooLarge)));
+  }
+  Ok((rest, v))
+}
+
+// Reads a whole word as a number, e.g. a top-level profile setting.
+pub fn number(text: &str) -> Option<f64> {
+  match number_val(text.as_bytes()) {
+    Ok(([], v)) => Some(v),
+    _ => None,
+  }
+}
+
+fn push_char(out: &mut Vec<u8>, code: u32) {
+  let c = char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER);
+  out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+fn skip_blank(i: &[u8]) -> &[u8] {
+  let n = i.iter().take_while(|c| is_space(**c)).count();
+  &i[n..]
+}
+
+// Decodes a Tcl backslash sequence; `i` starts right after the backslash.
+fn backslash<'a>(i: &'a [u8], out: &mut Vec<u8>) -> &'a [u8] {
+  let (c, rest) = match i.split_first() {
+    Some((c, rest)) => (*c, rest),
+    None => {
+      out.push(b'\\');
+      return i;
+    }
+  };
+
+  let (radix, max) = match c {
+    b'a' => return push_and(out, 0x07, rest),
+    b'b' => return push_and(out, 0x08, rest),
+    b'f' => return push_and(out, 0x0c, rest),
+    b'n' => return push_and(out, b'\n', rest),
+    b'r' => return push_and(out, b'\r', rest),
+    b't' => return push_and(out, b'\t', rest),
+    b'v' => return push_and(out, 0x0b, rest),
+    b'\n' => {
+      out.push(b' ');
+      return skip_blank(rest);
+    }
+    b'0'..=b'7' => (8, 3),
+    b'x' => (16, 2),
+    b'u' => (16, 4),
+    b'U' => (16, 8),
+    _ => return push_and(out, c, rest),
+  };
+
+  // octal digits start at `c` itself, the others after the marker letter
+  let digits = if radix == 8 { i } else { rest };
+  let n = digits
+    .iter()
+    .take(max)
+    .take_while(|d| (**d as char).is_digit(radix))
+    .count();
+  if n == 0 {
+    return push_and(out, c, rest);
   }
+
+  let code = str::from_utf8(&digits[..n])
+    .ok()
+    .and_then(|v| u32::from_str_radix(v, radix).ok())
+    .unwrap_or_default();
+  push_char(out, if radix == 8 { code & 0xff } else { code });
+  &digits[n..]
+}
+
+fn push_and<'a>(out: &mut Vec<u8>, c: u8, rest: &'a [u8]) -> &'a [u8] {
+  out.push(c);
+  rest
 }
 
+// A bare Tcl word, ending at whitespace or the `}` closing the step.
 fn plain_string_val(i: &[u8]) -> IResult<&[u8], String> {
-  let (i, v) = take_till(|c| is_space(c) || is_newline(c))(i)?;
-  Ok((
-    i,
-    String::from_utf8(v.to_vec()).expect("should be converted"),
-  ))
+  if i.starts_with(b"{") || i.starts_with(b"\"") {
+    return Err(nom::Err::Error(Error::new(i, ErrorKind::Char)));
+  }
+
+  let mut rest = i;
+  let mut out = vec![];
+  while let Some((c, r)) = rest.split_first() {
+    match c {
+      b'\\' => rest = backslash(r, &mut out),
+      c if c.is_ascii_whitespace() || *c == b'}' => break,
+      c => {
+        out.push(*c);
+        rest = r;
+      }
+    }
+  }
+  if rest.len() == i.len() {
+    return Err(nom::Err::Error(Error::new(i, ErrorKind::TakeTill1)));
+  }
+  Ok((rest, String::from_utf8(out).expect("should be converted")))
+}
+
+// A `"..."` word; only backslash sequences are substituted, as in a Tcl list.
+fn quoted_string_val(i: &[u8]) -> IResult<&[u8], String> {
+  let (mut rest, _) = tag("\"")(i)?;
+  let mut out = vec![];
+  loop {
+    match rest.split_first() {
+      Some((b'"', r)) => {
+        return Ok((r, String::from_utf8(out).expect("should be converted")));
+      }
+      Some((b'\\', r)) => rest = backslash(r, &mut out),
+      Some((c, r)) => {
+        out.push(*c);
+        rest = r;
+      }
+      None => return Err(nom::Err::Error(Error::new(i, ErrorKind::Char))),
+    }
+  }
 }
 
+// A `{...}` word. Braces nest, and the content is taken literally except for
+// backslash-newline; an escaped brace doesn't count towards nesting.
 fn bracket_string_val(i: &[u8]) -> IResult<&[u8], String> {
-  let (i, (_, v, _)) = tuple((tag("{"), take_until("}"), tag("}")))(i)?;
-  Ok((
-    i,
-    String::from_utf8(v.to_vec()).expect("should be converted"),
-  ))
+  let (mut rest, _) = tag("{")(i)?;
+  let mut out = vec![];
+  let mut depth = 1;
+  loop {
+    match rest.split_first() {
+      Some((b'\\', r)) => match r.split_first() {
+        Some((b'\n', r)) => {
+          out.push(b' ');
+          rest = skip_blank(r);
+        }
+        Some((c, r)) => {
+          out.extend_from_slice(&[b'\\', *c]);
+          rest = r;
+        }
+        None => return Err(nom::Err::Error(Error::new(i, ErrorKind::Char))),
+      },
+      Some((c, r)) => {
+        match c {
+          b'{' => depth += 1,
+          b'}' => depth -= 1,
+          _ => (),
+        }
+        if depth == 0 {
+          return Ok((r, String::from_utf8(out).expect("should be converted")));
+        }
+        out.push(*c);
+        rest = r;
+      }
+      None => return Err(nom::Err::Error(Error::new(i, ErrorKind::Char))),
+    }
+  }
 }
 
-fn string_val(i: &[u8]) -> IResult<&[u8], String> {
-  alt((bracket_string_val, plain_string_val))(i)
+pub fn string_val(i: &[u8]) -> IResult<&[u8], String> {
+  alt((bracket_string_val, quoted_string_val, plain_string_val))(i)
 }
 
 fn prop_bool(name: &str) -> impl Fn(&[u8]) -> IResult<&[u8], Prop> {
@@ -350,18 +455,33 @@ where
   |i: &[u8]| E::parse(i)
 }
 
-pub fn prop_string(name: &str) -> impl Fn(&[u8]) -> IResult<&[u8], Prop> {
+fn prop_string(name: &str) -> impl Fn(&[u8]) -> IResult<&[u8], Prop> {
   let name = name.to_string();
   move |i: &[u8]| {
     let (i, (_, _, val)) = tuple((tag(name.as_bytes()), space1, string_val))(i)?;
     let prop = match name.as_str() {
       "name" => Prop::Name(val),
-      _ => Prop::Unknown((name.clone(), format!("{}", val))),
+      _ => Prop::Unknown((name.clone(), val)),
     };
     Ok((i, prop))
   }
 }
 
+// Keeps any property this parser doesn't know about, e.g. keys added by
+// newer versions of the DE1 app, with its value as written.
+fn prop_unknown(i: &[u8]) -> IResult<&[u8], Prop> {
+  let (rest, (name, _, val)) = tuple((
+    take_while1(|c: u8| c.is_ascii_alphanumeric() || c == b'_'),
+    space1,
+    string_val,
+  ))(i)?;
+  if expected_value(name).is_some() {
+    return Err(nom::Err::Error(Error::new(i, ErrorKind::Verify)));
+  }
+  let name = String::from_utf8(name.to_vec()).expect("should be converted");
+  Ok((rest, Prop::Unknown((name, val))))
+}
+
 fn prop(i: &[u8]) -> IResult<&[u8], Prop> {
   alt((
     prop_bool("exit_if"),
@@ -382,6 +502,7 @@ fn prop(i: &[u8]) -> IResult<&[u8], Prop> {
     prop_number("exit_pressure_under"),
     prop_number("seconds"),
     prop_number("weight"),
+    prop_unknown,
   ))(i)
 }
 
@@ -398,19 +519,318 @@ pub fn steps(i: &[u8]) -> IResult<&[u8], Vec<Step>> {
   separated_list0(multispace0, step)(i)
 }
 
+// Where in the text a diagnostic points; `line` and `column` count from 1,
+// columns in characters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+  pub offset: usize,
+  pub line: usize,
+  pub column: usize,
+}
+
+impl Position {
+  pub fn at(input: &[u8], offset: usize) -> Self {
+    let line_start = input[..offset]
+      .iter()
+      .rposition(|c| is_newline(*c))
+      .map_or(0, |pos| pos + 1);
+    Self {
+      offset,
+      line: input[..offset].iter().filter(|c| is_newline(**c)).count() + 1,
+      column: String::from_utf8_lossy(&input[line_start..offset])
+        .chars()
+        .count()
+        + 1,
+    }
+  }
+}
+
+// `position` is `None` for problems that aren't tied to a place in the text,
+// such as a JSON value of the wrong kind.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+  pub position: Option<Position>,
+  pub step: Option<usize>,
+  pub prop: Option<String>,
+  pub expected: String,
+}
+
+impl Diagnostic {
+  fn at(input: &[u8], rest: &[u8], step: usize, prop: Option<&[u8]>, expected: &str) -> Self {
+    Self {
+      position: Some(Position::at(input, input.len() - rest.len())),
+      step: Some(step),
+      prop: prop.map(|name| String::from_utf8_lossy(name).into_owned()),
+      expected: expected.into(),
+    }
+  }
+}
+
+impl fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if let Some(position) = self.position {
+      write!(f, "line {}, column {}: ", position.line, position.column)?;
+    }
+    write!(f, "expected {}", self.expected)?;
+    match (self.step, &self.prop) {
+      (Some(step), Some(prop)) => write!(f, " (step {}, `{}`)", step + 1, prop),
+      (Some(step), None) => write!(f, " (step {})", step + 1),
+      _ => Ok(()),
+    }
+  }
+}
+
+const NUMBER: &str = "a number";
+
+fn number_out_of_range(i: &[u8]) -> bool {
+  matches!(
+    number_val(i),
+    Err(nom::Err::Error(Error {
+      code: ErrorKind::TooLarge,
+      ..
+    }))
+  )
+}
+
+fn expected_value(name: &[u8]) -> Option<&'static str> {
+  let expected = match name {
+    b"exit_if" => "`0` or `1`",
+    b"transition" => "`fast` or `smooth`",
+    b"sensor" => "`coffee` or `water`",
+    b"pump" => "`flow` or `pressure`",
+    b"exit_type" => "one of `pressure_under`, `pressure_over`, `flow_under` or `flow_over`",
+    b"name" => "a word, `{...}` or `\"...\"`",
+    b"flow" | b"volume" | b"max_flow_or_pressure_range" | b"exit_flow_under" | b"temperature"
+    | b"pressure" | b"exit_flow_over" | b"exit_pressure_over" | b"max_flow_or_pressure"
+    | b"exit_pressure_under" | b"seconds" | b"weight" => NUMBER,
+    _ => return None,
+  };
+  Some(expected)
+}
+
+fn skip_space(i: &[u8]) -> &[u8] {
+  let n = i.iter().take_while(|c| c.is_ascii_whitespace()).count();
+  &i[n..]
+}
+
+fn prop_name(i: &[u8]) -> &[u8] {
+  let n = i
+    .iter()
+    .take_while(|c| c.is_ascii_alphanumeric() || **c == b'_')
+    .count();
+  &i[..n]
+}
+
+// Skips one value word, balancing braces, so parsing can resume after a
+// malformed property.
+fn skip_word(i: &[u8]) -> &[u8] {
+  if !i.starts_with(b"{") {
+    let n = i
+      .iter()
+      .take_while(|c| !c.is_ascii_whitespace() && **c != b'}')
+      .count();
+    return &i[n..];
+  }
+
+  let mut depth = 0;
+  for (n, c) in i.iter().enumerate() {
+    match c {
+      b'{' => depth += 1,
+      b'}' => {
+        depth -= 1;
+        if depth == 0 {
+          return &i[n + 1..];
+        }
+      }
+      _ => (),
+    }
+  }
+  &i[i.len()..]
+}
+
+// Walks a single step property by property, so a failure can be reported
+// with the position and name of the offending property. Malformed
+// properties, including those `AdvancedStep` rejects, are dropped and the
+// rest of the step is kept; a step that is never closed is dropped as a whole.
+fn recover_step<'a>(
+  input: &'a [u8],
+  i: &'a [u8],
+  index: usize,
+  errors: &mut Vec<Diagnostic>,
+) -> (&'a [u8], Option<Step>) {
+  let mut i = &i[1..];
+  let mut list = vec![];
+  let mut starts = vec![];
+  loop {
+    i = skip_space(i);
+    match i.first() {
+      None => {
+        errors.push(Diagnostic::at(input, i, index, None, "`}` to close the step"));
+        return (i, None);
+      }
+      Some(b'}') => {
+        let mut step = Step(list);
+        while let Err(err) = AdvancedStep::try_from(&step) {
+          let prop = step.0.remove(err.index);
+          let at = starts.remove(err.index);
+          errors.push(Diagnostic::at(
+            input,
+            at,
+            index,
+            Some(prop.name().as_bytes()),
+            err.expected(),
+          ));
+        }
+        return (&i[1..], Some(step));
+      }
+      _ => (),
+    }
+
+    let name = prop_name(i);
+    match prop(i) {
+      Ok((rest, v)) => match rest.first() {
+        Some(c) if !c.is_ascii_whitespace() && *c != b'}' => {
+          errors.push(Diagnostic::at(
+            input,
+            rest,
+            index,
+            Some(name),
+            "whitespace or `}` after the value",
+          ));
+          i = skip_word(rest);
+        }
+        _ => {
+          list.push(v);
+          starts.push(i);
+          i = rest;
+        }
+      },
+      Err(_) if name.is_empty() => {
+        errors.push(Diagnostic::at(input, i, index, None, "a property name"));
+        i = skip_word(i);
+      }
+      Err(_) => {
+        let value = skip_space(&i[name.len()..]);
+        errors.push(match expected_value(name) {
+          Some(NUMBER) if number_out_of_range(value) => Diagnostic::at(
+            input,
+            value,
+            index,
+            Some(name),
+            "a number within the range of a double",
+          ),
+          Some(expected) => Diagnostic::at(input, value, index, Some(name), expected),
+          None => Diagnostic::at(input, value, index, Some(name), "a value"),
+        });
+        i = skip_word(value);
+      }
+    }
+  }
+}
+
+// Parses as many steps as possible, skipping malformed steps and
+// properties, and returns the recovered steps along with every error.
+pub fn parse_recover(text: &str) -> (Vec<Step>, Vec<Diagnostic>) {
+  let input = text.as_bytes();
+  let mut i = input;
+  let mut list = vec![];
+  let mut errors = vec![];
+  let mut dropped = 0;
+  loop {
+    let rest = skip_space(i);
+    if rest.is_empty() {
+      return (list, errors);
+    }
+
+    let index = list.len() + dropped;
+    if !rest.starts_with(b"{") {
+      errors.push(Diagnostic::at(input, rest, index, None, "`{` to start a step"));
+      let n = rest.iter().position(|c| *c == b'{').unwrap_or(rest.len());
+      i = &rest[n..];
+      continue;
+    }
+
+    // well-formed steps that `AdvancedStep` accepts are taken as they are
+    if let Ok((after, step)) = step(rest) {
+      if AdvancedStep::try_from(&step).is_ok() {
+        list.push(step);
+        i = after;
+        continue;
+      }
+    }
+
+    let (rest, step) = recover_step(input, rest, index, &mut errors);
+    match step {
+      Some(step) => list.push(step),
+      None => dropped += 1,
+    }
+    i = rest;
+  }
+}
+
+pub fn parse(text: &str) -> Result<Vec<Step>, Diagnostic> {
+  let (list, errors) = parse_recover(text);
+  match errors.into_iter().next() {
+    Some(err) => Err(err),
+    None => Ok(list),
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use nom::error::{Error, ErrorKind};
 
   use super::*;
 
+  fn line(e: &Diagnostic) -> usize {
+    e.position.unwrap().line
+  }
+
+  fn column(e: &Diagnostic) -> usize {
+    e.position.unwrap().column
+  }
+
   #[test]
   fn test_number_val() {
-    assert_eq!(number_val(b"8;"), Ok((&b";"[..], 8.0f32)));
-    assert_eq!(number_val(b"80;"), Ok((&b";"[..], 80.0f32)));
-    assert_eq!(number_val(b"8.;"), Ok((&b";"[..], 8.0f32)));
-    assert_eq!(number_val(b"8.0;"), Ok((&b";"[..], 8.0f32)));
-    assert_eq!(number_val(b".8;"), Ok((&b";"[..], 0.8f32)));
+    assert_eq!(number_val(b"8;"), Ok((&b";"[..], 8.0f64)));
+    assert_eq!(number_val(b"80;"), Ok((&b";"[..], 80.0f64)));
+    assert_eq!(number_val(b"8.;"), Ok((&b";"[..], 8.0f64)));
+    assert_eq!(number_val(b"8.0;"), Ok((&b";"[..], 8.0f64)));
+    assert_eq!(number_val(b".8;"), Ok((&b";"[..], 0.8f64)));
+  }
+
+  #[test]
+  fn test_number_val_full() {
+    assert_eq!(number_val(b"70000;"), Ok((&b";"[..], 70000.0f64)));
+    assert_eq!(number_val(b"-1;"), Ok((&b";"[..], -1.0f64)));
+    assert_eq!(number_val(b"+2;"), Ok((&b";"[..], 2.0f64)));
+    assert_eq!(number_val(b"-.5;"), Ok((&b";"[..], -0.5f64)));
+    assert_eq!(number_val(b"1e-3;"), Ok((&b";"[..], 0.001f64)));
+    assert_eq!(number_val(b"2.5E+2;"), Ok((&b";"[..], 250.0f64)));
+    assert_eq!(number_val(b"1e;"), Ok((&b"e;"[..], 1.0f64)));
+    assert_eq!(number_val(b"8"), Ok((&b""[..], 8.0f64)));
+    assert_eq!(number_val(b"0e5;"), Ok((&b";"[..], 0.0f64)));
+    assert_eq!(
+      number_val(b"1e400;"),
+      Err(nom::Err::Error(Error::new(&b"1e400;"[..], ErrorKind::TooLarge)))
+    );
+  }
+
+  #[test]
+  fn test_number_val_precision() {
+    assert_eq!(number_val(b"16777217;"), Ok((&b";"[..], 16777217.0f64)));
+    assert_eq!(number_val(b"0.1;"), Ok((&b";"[..], 0.1f64)));
+    assert_eq!(number_val(b"1e-50;"), Ok((&b";"[..], 1e-50f64)));
+    assert_eq!(
+      number_val(b"1e-400;"),
+      Err(nom::Err::Error(Error::new(&b"1e-400;"[..], ErrorKind::TooLarge)))
+    );
+    assert_eq!(
+      number_val(b"2.5e-320;"),
+      Err(nom::Err::Error(Error::new(&b"2.5e-320;"[..], ErrorKind::TooLarge)))
+    );
+    assert!(number_val(b"-;").is_err());
+    assert!(number_val(b".;").is_err());
   }
 
   #[test]
@@ -513,7 +933,7 @@ mod tests {
     assert_eq!(
       props(tcl.as_bytes()),
       Ok((
-        &b".00"[..],
+        &b""[..],
         vec![
           Prop::ExitIf(true),
           Prop::Flow(8.0),
@@ -627,4 +1047,243 @@ mod tests {
       ))
     );
   }
+
+  #[test]
+  fn test_parse() {
+    let tcl = include_str!("../fixtures/steps.inner.xxx");
+    assert_eq!(parse(tcl).map(|list| list.len()), Ok(7));
+    assert_eq!(parse(" \n"), Ok(vec![]));
+    assert_eq!(
+      parse("{flow 8}\n{seconds 4}"),
+      Ok(vec![
+        Step(vec![Prop::Flow(8.0)]),
+        Step(vec![Prop::Seconds(4.0)])
+      ])
+    );
+  }
+
+  #[test]
+  fn test_parse_diagnostic() {
+    assert_eq!(
+      parse("{flow 8}\n{seconds 4 pressure x}"),
+      Err(Diagnostic {
+        position: Some(Position {
+          offset: 29,
+          line: 2,
+          column: 21,
+        }),
+        step: Some(1),
+        prop: Some("pressure".into()),
+        expected: "a number".into(),
+      })
+    );
+    assert_eq!(
+      parse("{flow 8 transition slow}").map_err(|e| (column(&e), e.expected)),
+      Err((20, "`fast` or `smooth`".into()))
+    );
+    assert_eq!(
+      parse("{flow 8 colour}").map_err(|e| (column(&e), e.prop, e.expected)),
+      Err((15, Some("colour".into()), "a value".into()))
+    );
+    assert_eq!(
+      parse("{flow 8x}").map_err(|e| (column(&e), e.expected)),
+      Err((8, "whitespace or `}` after the value".into()))
+    );
+    assert_eq!(
+      parse("{flow 8} flow").map_err(|e| (column(&e), e.step, e.expected)),
+      Err((10, Some(1), "`{` to start a step".into()))
+    );
+    assert_eq!(
+      parse("{flow 8").map_err(|e| (column(&e), e.expected)),
+      Err((8, "`}` to close the step".into()))
+    );
+  }
+
+  #[test]
+  fn test_diagnostic_display() {
+    let err = parse("{flow 8}\n{seconds 4 pressure x}").unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "line 2, column 21: expected a number (step 2, `pressure`)"
+    );
+  }
+
+  #[test]
+  fn test_parse_recover() {
+    let (list, errors) = parse_recover("{flow 8 pressure x seconds 4}\n{flow 2");
+    assert_eq!(list, vec![Step(vec![Prop::Flow(8.0), Prop::Seconds(4.0)])]);
+    assert_eq!(
+      errors
+        .iter()
+        .map(|e| (line(e), column(e), e.step, e.expected.as_str()))
+        .collect::<Vec<_>>(),
+      vec![
+        (1, 18, Some(0), "a number"),
+        (2, 8, Some(1), "`}` to close the step"),
+      ]
+    );
+
+    let (list, errors) =
+      parse_recover("{flow 8} garbage {pump {dark red} seconds 4}\n{name {Pressure Up}}");
+    assert_eq!(
+      list,
+      vec![
+        Step(vec![Prop::Flow(8.0)]),
+        Step(vec![Prop::Seconds(4.0)]),
+        Step(vec![Prop::Name("Pressure Up".into())]),
+      ]
+    );
+    assert_eq!(
+      errors
+        .iter()
+        .map(|e| (column(e), e.step, e.prop.as_deref()))
+        .collect::<Vec<_>>(),
+      vec![(10, Some(1), None), (24, Some(1), Some("pump"))]
+    );
+
+    let (list, errors) = parse_recover("{flow 8x seconds 4} {seconds 2}");
+    assert_eq!(
+      list,
+      vec![
+        Step(vec![Prop::Seconds(4.0)]),
+        Step(vec![Prop::Seconds(2.0)])
+      ]
+    );
+    assert_eq!(errors.len(), 1);
+  }
+
+  #[test]
+  fn test_prop_unknown() {
+    assert_eq!(
+      prop(b"exit_weight 2.5}"),
+      Ok((
+        &b"}"[..],
+        Prop::Unknown(("exit_weight".into(), "2.5".into()))
+      ))
+    );
+    assert_eq!(
+      prop(b"popup {Stir now}}"),
+      Ok((
+        &b"}"[..],
+        Prop::Unknown(("popup".into(), "Stir now".into()))
+      ))
+    );
+    assert_eq!(
+      prop(b"flow x}"),
+      Err(nom::Err::Error(Error::new(&b"flow x}"[..], ErrorKind::Verify)))
+    );
+    assert_eq!(
+      parse("{limiter_value 3 flow 8 popup {} seconds 2}"),
+      Ok(vec![Step(vec![
+        Prop::Unknown(("limiter_value".into(), "3".into())),
+        Prop::Flow(8.0),
+        Prop::Unknown(("popup".into(), "".into())),
+        Prop::Seconds(2.0),
+      ])])
+    );
+  }
+
+  #[test]
+  fn test_string_val_quoting() {
+    assert_eq!(
+      string_val(b"{Bloom {long} soak} "),
+      Ok((&b" "[..], "Bloom {long} soak".into()))
+    );
+    assert_eq!(
+      string_val(b"{a \\} b} "),
+      Ok((&b" "[..], "a \\} b".into()))
+    );
+    assert_eq!(
+      string_val(b"{line\\\n    continued}}"),
+      Ok((&b"}"[..], "line continued".into()))
+    );
+    assert_eq!(
+      string_val(b"\"Pressure Up\" "),
+      Ok((&b" "[..], "Pressure Up".into()))
+    );
+    assert_eq!(
+      string_val(b"\"say \\\"hi\\\"\\n\\t{x}\" "),
+      Ok((&b" "[..], "say \"hi\"\n\t{x}".into()))
+    );
+    assert_eq!(
+      string_val(b"Pressure\\ Up\\}}"),
+      Ok((&b"}"[..], "Pressure Up}".into()))
+    );
+    assert_eq!(
+      string_val(b"caf\\u00e9\\x21\\101\\q "),
+      Ok((&b" "[..], "caf\u{e9}!Aq".into()))
+    );
+    assert!(string_val(b"{unterminated").is_err());
+    assert!(string_val(b"\"unterminated").is_err());
+    assert!(string_val(b"}").is_err());
+  }
+
+  #[test]
+  fn test_prop_string_quoting() {
+    let prop_name = prop_string("name");
+    assert_eq!(
+      prop_name(b"name {Rise {fast}}\n"),
+      Ok((&b"\n"[..], Prop::Name("Rise {fast}".into())))
+    );
+    assert_eq!(
+      prop_name(b"name \"9 bar\\thold\"\n"),
+      Ok((&b"\n"[..], Prop::Name("9 bar\thold".into())))
+    );
+  }
+
+  #[test]
+  fn test_parse_numbers() {
+    assert_eq!(
+      parse("{volume 70000 exit_pressure_under -1 seconds 1e1}"),
+      Ok(vec![Step(vec![
+        Prop::Volume(70000.0),
+        Prop::ExitPressureUnder(-1.0),
+        Prop::Seconds(10.0),
+      ])])
+    );
+    assert_eq!(
+      parse("{seconds 1e999}").map_err(|e| (column(&e), e.expected)),
+      Err((10, "a number within the range of a double".into()))
+    );
+  }
+
+  #[test]
+  fn test_parse_invalid_step() {
+    // with and without the trailing newline of a textarea or file
+    for text in [
+      "{seconds 4 flow 2 seconds 5} {flow -2 seconds 1}",
+      "{seconds 4 flow 2 seconds 5} {flow -2 seconds 1}\n",
+    ]
+    .iter()
+    {
+      let (list, errors) = parse_recover(text);
+      assert_eq!(
+        list,
+        vec![
+          Step(vec![Prop::Seconds(4.0), Prop::Flow(2.0)]),
+          Step(vec![Prop::Seconds(1.0)])
+        ]
+      );
+      assert_eq!(
+        errors
+          .iter()
+          .map(|e| (column(e), e.step, e.prop.as_deref(), e.expected.as_str()))
+          .collect::<Vec<_>>(),
+        vec![
+          (19, Some(0), Some("seconds"), "each property only once per step"),
+          (31, Some(1), Some("flow"), "a value of 0 or more"),
+        ]
+      );
+    }
+
+    let (list, errors) = parse_recover("{seconds 3}\n{flow -2 seconds 1}\n{seconds 5}\n");
+    assert_eq!(list.len(), 3);
+    assert_eq!(
+      errors
+        .iter()
+        .map(|e| (line(e), column(e), e.step))
+        .collect::<Vec<_>>(),
+      vec![(2, 2, Some(1))]
+    );
+  }
 }
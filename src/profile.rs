@@ -1,152 +1,432 @@
 use std::collections::HashMap;
+use std::fmt;
 
 use include_dir::{include_dir, Dir};
 use once_cell::sync::Lazy;
 
-use crate::parser::{prop_string, Prop, PumpType, Step, TransitionType};
+use crate::document::ProfileDocument;
+use crate::json;
+use crate::parser::{ExitType, PumpType, TransitionType};
+use crate::serializer::steps;
+use crate::step::{advanced_steps, AdvancedStep, MAX_SECONDS};
 
-static PROFILES_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/profiles");
+pub static PROFILES_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/profiles");
 
 pub static PROFILES: Lazy<HashMap<String, Preset>> = Lazy::new(|| {
-  let parse_title = prop_string("profile_title");
-  let parse_notes = prop_string("profile_notes");
-
   let mut map = HashMap::default();
   for file in PROFILES_DIR.files() {
-    let mut preset = Preset::default();
     let file_name = file.path().file_name().unwrap().to_str().unwrap();
-    let data = file.contents_utf8().unwrap().to_string();
-
-    // filter by "Advanced" profile (settings_2c)
-    if data.find("settings_2c").is_none() {
-      continue;
-    }
-
-    for line in data.lines() {
-      if line.starts_with("advanced_shot") {
-        let end = line.len() - 1;
-        preset.data = format!("{}\n", line[15..end].to_string());
-      } else if line.starts_with("profile_title") {
-        if let Ok((_, Prop::Unknown((_, title)))) = parse_title(line.as_bytes()) {
-          preset.title = title;
-        }
-      } else if line.starts_with("profile_notes") {
-        if let Ok((_, Prop::Unknown((_, notes)))) = parse_notes(line.as_bytes()) {
-          preset.notes = notes;
-        }
-      }
-    }
+    let preset = match Preset::load(file.contents_utf8().unwrap(), Source::Bundled) {
+      Ok(preset) => preset,
+      Err(_) => continue,
+    };
     map.insert(file_name.to_string(), preset);
   }
   map
 });
 
-#[derive(Clone, Default)]
+// The profiles the round-trip tests run over: one of each type from
+// `fixtures/`, since `profiles/` may be empty, then the bundled ones.
+#[cfg(test)]
+pub fn fixture_profiles() -> impl Iterator<Item = &'static str> {
+  const FIXTURES: &[&str] = &[
+    include_str!("../fixtures/profile.advanced"),
+    include_str!("../fixtures/profile.pressure"),
+    include_str!("../fixtures/profile.flow"),
+  ];
+  let presets = PROFILES_DIR
+    .files()
+    .map(|file| file.contents_utf8().unwrap());
+  FIXTURES.iter().copied().chain(presets)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+  Bundled,
+  User,
+}
+
+impl fmt::Display for Source {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let label = match self {
+      Source::Bundled => "bundled",
+      Source::User => "user",
+    };
+    write!(f, "{}", label)
+  }
+}
+
+#[derive(Clone)]
 pub struct Preset {
   pub title: String,
   pub notes: String,
   pub data: String,
+  pub document: ProfileDocument,
+  pub steps: Vec<AdvancedStep>,
+  pub source: Source,
 }
 
-pub type PositionList = Vec<(f64, f64, f64, f64)>;
+impl Preset {
+  // Reads a `.tcl` or JSON v2 profile.
+  pub fn load(text: &str, source: Source) -> Result<Self, String> {
+    let document = if json::is_json(text) {
+      json::read(text).map_err(|e| e.to_string())?
+    } else {
+      ProfileDocument::parse(text).map_err(|e| e.to_string())?
+    };
 
-pub fn analyze(steps: &Vec<Step>) -> (PositionList, PositionList, PositionList, f64) {
-  let mut temperature_pos: PositionList = vec![];
-  let mut last_temperature_pos: Option<(f64, f64, f64, f64)> = None;
+    let shot_steps = document.shot_steps();
+    Ok(Preset {
+      title: document.title.clone().unwrap_or_default(),
+      notes: document.notes.clone().unwrap_or_default(),
+      data: format!("{}\n", steps(&shot_steps)),
+      document,
+      steps: advanced_steps(&shot_steps),
+      source,
+    })
+  }
+}
 
-  let mut pressure_pos: PositionList = vec![];
-  let mut last_pressure_pos: Option<(f64, f64, f64, f64)> = None;
+// Simulation tick in seconds.
+const TICK: f64 = 0.1;
+// The most the pump delivers, in ml/s.
+const PUMP_MAX_FLOW: f64 = 8.;
+// Slack for volumes summed up tick by tick.
+const EPSILON: f64 = 1e-6;
 
-  let mut flow_pos: PositionList = vec![];
-  let mut last_flow_pos: Option<(f64, f64, f64, f64)> = None;
+// A deliberately simple hydraulic model of the coffee bed: pressure can only
+// build once the headspace above the puck is full, after that it follows
+// `pressure = resistance * flow`. Resistance falls from `initial_resistance`
+// towards `final_resistance` as the puck erodes, with `erosion_time` as the
+// time constant counted from when the puck is wet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PuckModel {
+  // ml of water needed before pressure builds
+  pub headspace: f64,
+  // g of ground coffee
+  pub dose: f64,
+  // ml of water each g of coffee holds back before anything drips
+  pub absorption: f64,
+  // bar per ml/s
+  pub initial_resistance: f64,
+  pub final_resistance: f64,
+  // seconds
+  pub erosion_time: f64,
+}
 
-  let mut elapsed_time = 0f64;
-  let mut prev_pump = None;
-  let mut prev_exit_flow: Option<f32> = None;
+impl Default for PuckModel {
+  fn default() -> Self {
+    Self {
+      headspace: 6.,
+      dose: 18.,
+      absorption: 1.1,
+      initial_resistance: 4.,
+      final_resistance: 2.,
+      erosion_time: 30.,
+    }
+  }
+}
 
-  for step in steps.iter() {
-    let duration = step.seconds() as f64;
-    let transition = step.transition();
-    let pump = step.pump();
+impl PuckModel {
+  pub fn resistance(&self, wet_time: f64) -> f64 {
+    let decay = if self.erosion_time > 0. {
+      (-wet_time / self.erosion_time).exp()
+    } else {
+      0.
+    };
+    self.final_resistance + (self.initial_resistance - self.final_resistance) * decay
+  }
 
-    for prop in step.0.iter() {
-      match prop {
-        Prop::Temperature(t) => {
-          let t = *t as f64;
-          if let Some((.., prev_t)) = last_temperature_pos {
-            temperature_pos.push((elapsed_time, prev_t, elapsed_time, t));
-            temperature_pos.push((elapsed_time, t, elapsed_time + duration, t));
-          } else {
-            temperature_pos.push((elapsed_time, t, elapsed_time + duration, t));
-          }
-          last_temperature_pos = Some(temperature_pos.last().unwrap().clone());
-        }
-        Prop::Pressure(v) => {
-          if pump == PumpType::Pressure {
-            if let (Some(PumpType::Flow), Some((.., px, py))) = (prev_pump, last_flow_pos) {
-              flow_pos.push((px, py, px, 0.));
-              last_flow_pos = Some(flow_pos.last().unwrap().clone());
-            }
-
-            let v = *v as f64;
-            if let Some((.., prev_v)) = last_pressure_pos {
-              match transition {
-                TransitionType::Fast => {
-                  pressure_pos.push((elapsed_time, prev_v, elapsed_time, v));
-                  pressure_pos.push((elapsed_time, v, elapsed_time + duration, v));
-                }
-                TransitionType::Smooth => {
-                  pressure_pos.push((elapsed_time, prev_v, elapsed_time + duration, v));
-                }
-              }
-            } else {
-              pressure_pos.push((elapsed_time, 0., elapsed_time, v));
-              pressure_pos.push((elapsed_time, v, elapsed_time + duration, v));
-            }
-
-            last_pressure_pos = Some(pressure_pos.last().unwrap().clone());
-          }
+  // Beverage in the cup once `volume` ml were pumped, taking 1 ml as 1 g.
+  pub fn weight(&self, volume: f64) -> f64 {
+    (volume - self.headspace - self.dose * self.absorption).max(0.)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExitReason {
+  Time,
+  PressureOver,
+  PressureUnder,
+  FlowOver,
+  FlowUnder,
+  Volume,
+  Weight,
+}
+
+impl fmt::Display for ExitReason {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let label = match self {
+      ExitReason::Time => "time",
+      ExitReason::PressureOver => "pressure over",
+      ExitReason::PressureUnder => "pressure under",
+      ExitReason::FlowOver => "flow over",
+      ExitReason::FlowUnder => "flow under",
+      ExitReason::Volume => "volume",
+      ExitReason::Weight => "weight",
+    };
+    write!(f, "{}", label)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+  pub time: f64,
+  pub step: usize,
+  pub pressure: f64,
+  pub flow: f64,
+  // water pumped since the start of the shot, in ml
+  pub volume: f64,
+  // estimated beverage in the cup, in g
+  pub weight: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StepRun {
+  pub start: f64,
+  pub end: f64,
+  pub exit: ExitReason,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Simulation {
+  pub samples: Vec<Sample>,
+  // one per step, in order
+  pub steps: Vec<StepRun>,
+  pub duration: f64,
+}
+
+fn exit_reason(step: &AdvancedStep, sample: &Sample, step_volume: f64) -> Option<ExitReason> {
+  if let Some((exit_type, v)) = step.exit() {
+    let reason = match exit_type {
+      ExitType::PressureOver if sample.pressure > v => Some(ExitReason::PressureOver),
+      ExitType::PressureUnder if sample.pressure < v => Some(ExitReason::PressureUnder),
+      ExitType::FlowOver if sample.flow > v => Some(ExitReason::FlowOver),
+      ExitType::FlowUnder if sample.flow < v => Some(ExitReason::FlowUnder),
+      _ => None,
+    };
+    if reason.is_some() {
+      return reason;
+    }
+  }
+  if step.volume > 0. && step_volume + EPSILON >= step.volume {
+    return Some(ExitReason::Volume);
+  }
+  if step.weight > 0. && sample.weight + EPSILON >= step.weight {
+    return Some(ExitReason::Weight);
+  }
+  None
+}
+
+// Water pushed through the puck so far. `simulate` and the volume estimate
+// both advance it tick by tick, so they agree on what a step pumps.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pump {
+  // ml since the start of the shot
+  pub volume: f64,
+  // when the headspace filled up and the puck got wet
+  wet_since: Option<f64>,
+}
+
+impl Pump {
+  // Runs `step` for the tick of `tick` seconds that ends at `time`, aiming
+  // for `setpoint` on the series the step controls. Returns the pressure and
+  // flow during the tick.
+  pub fn tick(
+    &mut self,
+    step: &AdvancedStep,
+    setpoint: f64,
+    time: f64,
+    tick: f64,
+    puck: &PuckModel,
+  ) -> (f64, f64) {
+    let limit = step.max_flow_or_pressure;
+    let filled = self.volume + EPSILON >= puck.headspace;
+    let resistance = puck.resistance(self.wet_since.map_or(0., |wet| time - wet));
+    let (pressure, flow) = match (step.pump, filled) {
+      (PumpType::Flow, false) => (0., setpoint.min(PUMP_MAX_FLOW)),
+      (PumpType::Flow, true) => {
+        let q = setpoint.min(PUMP_MAX_FLOW);
+        if limit > 0. && q * resistance > limit {
+          (limit, limit / resistance)
+        } else {
+          (q * resistance, q)
         }
-        Prop::Flow(v) => {
-          if pump == PumpType::Flow {
-            if let (Some(PumpType::Pressure), Some((.., px, py))) = (prev_pump, last_pressure_pos) {
-              pressure_pos.push((px, py, px, 0.));
-              last_pressure_pos = Some(pressure_pos.last().unwrap().clone());
-            }
-
-            let v = *v as f64;
-            if let Some((.., prev_v)) = last_flow_pos {
-              let mut prev_v = prev_v;
-              if let Some(f) = prev_exit_flow {
-                flow_pos.push((elapsed_time, prev_v, elapsed_time, f as f64));
-                prev_v = f as f64;
-              }
-
-              match transition {
-                TransitionType::Fast => {
-                  flow_pos.push((elapsed_time, prev_v, elapsed_time, v));
-                  flow_pos.push((elapsed_time, v, elapsed_time + duration, v));
-                }
-                TransitionType::Smooth => {
-                  flow_pos.push((elapsed_time, prev_v, elapsed_time + duration, v));
-                }
-              }
-            } else {
-              flow_pos.push((elapsed_time, 0., elapsed_time, v));
-              flow_pos.push((elapsed_time, v, elapsed_time + duration, v));
-            }
-
-            last_flow_pos = Some(flow_pos.last().unwrap().clone());
-          }
+      }
+      (PumpType::Pressure, false) => {
+        let q = if setpoint > 0. { PUMP_MAX_FLOW } else { 0. };
+        (0., if limit > 0. { q.min(limit) } else { q })
+      }
+      (PumpType::Pressure, true) => {
+        let mut q = (setpoint / resistance).min(PUMP_MAX_FLOW);
+        if limit > 0. {
+          q = q.min(limit);
         }
-        _ => (),
+        (q * resistance, q)
+      }
+    };
+    self.volume += flow * tick;
+    if self.wet_since.is_none() && self.volume + EPSILON >= puck.headspace {
+      self.wet_since = Some(time);
+    }
+    (pressure, flow)
+  }
+}
+
+// Runs the steps against `puck` one tick at a time. A step ends at its first
+// met exit condition, or after `seconds`; the shot ends after `MAX_SECONDS`.
+pub fn simulate(steps: &[AdvancedStep], puck: &PuckModel) -> Simulation {
+  let max_time = MAX_SECONDS;
+  let mut simulation = Simulation::default();
+  let mut time = 0f64;
+  let mut pump = Pump::default();
+  let (mut pressure, mut flow) = (0f64, 0f64);
+
+  for (index, step) in steps.iter().enumerate() {
+    let start = time;
+    let seconds = step.seconds;
+    let from = match step.pump {
+      PumpType::Pressure => pressure,
+      PumpType::Flow => flow,
+    };
+    let target = step.target();
+    let mut step_volume = 0f64;
+    let mut exit = ExitReason::Time;
+
+    while time - start + TICK / 2. < seconds && time + TICK / 2. < max_time {
+      time += TICK;
+      let setpoint = match step.transition {
+        TransitionType::Fast => target,
+        TransitionType::Smooth => from + (target - from) * ((time - start) / seconds).min(1.),
+      };
+
+      let (p, q) = pump.tick(step, setpoint, time, TICK, puck);
+      pressure = p;
+      flow = q;
+      step_volume += flow * TICK;
+
+      let sample = Sample {
+        time,
+        step: index,
+        pressure,
+        flow,
+        volume: pump.volume,
+        weight: puck.weight(pump.volume),
+      };
+      simulation.samples.push(sample);
+      if let Some(reason) = exit_reason(step, &sample, step_volume) {
+        exit = reason;
+        break;
       }
     }
 
-    elapsed_time += duration;
-    prev_pump = Some(pump);
-    prev_exit_flow = step.exit_flow();
+    simulation.steps.push(StepRun {
+      start,
+      end: time,
+      exit,
+    });
+  }
+
+  simulation.duration = time;
+  simulation
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::step::target_step;
+
+  fn exits(simulation: &Simulation) -> Vec<ExitReason> {
+    simulation.steps.iter().map(|run| run.exit).collect()
+  }
+
+  #[test]
+  fn test_full_duration() {
+    let simulation = simulate(
+      &[
+        target_step(PumpType::Flow, 2., 10.),
+        target_step(PumpType::Flow, 2., 0.),
+      ],
+      &PuckModel::default(),
+    );
+    assert_eq!(exits(&simulation), vec![ExitReason::Time, ExitReason::Time]);
+    assert!((simulation.duration - 10.).abs() < 1e-9);
+    assert_eq!(simulation.samples.len(), 100);
+    let last = simulation.samples.last().unwrap();
+    assert!((last.volume - 20.).abs() < 1e-9);
+    assert!((last.pressure - 2. * PuckModel::default().resistance(7.)).abs() < 1e-9);
+  }
+
+  #[test]
+  fn test_exit_pressure_over() {
+    let fill = AdvancedStep {
+      exit_if: true,
+      exit_type: Some(ExitType::PressureOver),
+      exit_pressure_over: 4.,
+      ..target_step(PumpType::Flow, 4., 30.)
+    };
+    let simulation = simulate(
+      &[fill, target_step(PumpType::Pressure, 9., 20.)],
+      &PuckModel::default(),
+    );
+    assert_eq!(
+      exits(&simulation),
+      vec![ExitReason::PressureOver, ExitReason::Time]
+    );
+    // 6 ml of headspace at 4 ml/s, then 4 ml/s * 4 bar/(ml/s) is over 4 bar
+    assert!((simulation.steps[0].end - 1.6).abs() < 1e-9);
+    assert!((simulation.steps[1].start - 1.6).abs() < 1e-9);
   }
 
-  (temperature_pos, pressure_pos, flow_pos, elapsed_time)
+  #[test]
+  fn test_exit_volume_and_weight() {
+    let pour = AdvancedStep {
+      volume: 10.,
+      ..target_step(PumpType::Flow, 2., 30.)
+    };
+    let simulation = simulate(&[pour], &PuckModel::default());
+    assert_eq!(exits(&simulation), vec![ExitReason::Volume]);
+    assert!((simulation.duration - 5.).abs() < 1e-9);
+
+    let pour = AdvancedStep {
+      weight: 4.,
+      ..target_step(PumpType::Flow, 2., 30.)
+    };
+    let simulation = simulate(&[pour], &PuckModel::default());
+    assert_eq!(exits(&simulation), vec![ExitReason::Weight]);
+    // 6 ml headspace + 18 g * 1.1 ml/g absorbed + 4 g out at 2 ml/s
+    assert!((simulation.duration - 14.9).abs() < 1e-9);
+  }
+
+  #[test]
+  fn test_zero_resistance() {
+    let puck = PuckModel {
+      initial_resistance: 0.,
+      final_resistance: 0.,
+      ..PuckModel::default()
+    };
+    let simulation = simulate(&[target_step(PumpType::Pressure, 9., 10.)], &puck);
+    let last = simulation.samples.last().unwrap();
+    assert!((last.volume - 80.).abs() < 1e-6);
+  }
+
+  #[test]
+  fn test_max_seconds() {
+    let long = target_step(PumpType::Flow, 2., 3000.);
+    let simulation = simulate(&[long.clone(), long.clone(), long], &PuckModel::default());
+    assert!((simulation.duration - MAX_SECONDS).abs() < 1e-6);
+    assert_eq!(simulation.samples.len(), 36000);
+    assert_eq!(simulation.steps.len(), 3);
+    assert_eq!(simulation.steps[2].start, simulation.steps[2].end);
+  }
+
+  #[test]
+  fn test_limiter() {
+    let hold = AdvancedStep {
+      max_flow_or_pressure: 1.5,
+      ..target_step(PumpType::Pressure, 9., 20.)
+    };
+    let simulation = simulate(&[hold], &PuckModel::default());
+    assert!(simulation.samples.iter().all(|s| s.flow <= 1.5));
+  }
 }
@@ -0,0 +1,205 @@
+use crate::parser::{ExitType, Prop, PumpType, SensorType, Step, TransitionType};
+
+// Quotes a value as a single Tcl list element. Braces are preferred since
+// they keep the text readable; words that can't be braced safely fall back
+// to backslash escapes.
+pub fn word(s: &str) -> String {
+  if s.is_empty() {
+    return "{}".into();
+  }
+
+  let special = |c: char| c.is_whitespace() || "{}[]$\"\\;".contains(c);
+  if !s.contains(special) && !s.starts_with('#') {
+    return s.into();
+  }
+
+  if !s.contains('\\') && balanced(s) {
+    return format!("{{{}}}", s);
+  }
+
+  let mut out = String::with_capacity(s.len());
+  for c in s.chars() {
+    match c {
+      '\n' => out.push_str("\\n"),
+      '\t' => out.push_str("\\t"),
+      c if special(c) => {
+        out.push('\\');
+        out.push(c);
+      }
+      c => out.push(c),
+    }
+  }
+  out
+}
+
+fn balanced(s: &str) -> bool {
+  let mut depth = 0;
+  for c in s.chars() {
+    match c {
+      '{' => depth += 1,
+      '}' if depth == 0 => return false,
+      '}' => depth -= 1,
+      _ => (),
+    }
+  }
+  depth == 0
+}
+
+// `Display` for `f64` writes the shortest text that parses back to the same
+// value, without exponents, so `94.00` is written as `94` and `0.6` stays `0.6`.
+pub fn number(v: f64) -> String {
+  format!("{}", v)
+}
+
+fn bool_val(v: bool) -> &'static str {
+  if v {
+    "1"
+  } else {
+    "0"
+  }
+}
+
+fn transition_val(v: TransitionType) -> &'static str {
+  match v {
+    TransitionType::Fast => "fast",
+    TransitionType::Smooth => "smooth",
+  }
+}
+
+fn sensor_val(v: SensorType) -> &'static str {
+  match v {
+    SensorType::Coffee => "coffee",
+    SensorType::Water => "water",
+  }
+}
+
+fn pump_val(v: PumpType) -> &'static str {
+  match v {
+    PumpType::Flow => "flow",
+    PumpType::Pressure => "pressure",
+  }
+}
+
+fn exit_type_val(v: ExitType) -> &'static str {
+  match v {
+    ExitType::PressureUnder => "pressure_under",
+    ExitType::PressureOver => "pressure_over",
+    ExitType::FlowUnder => "flow_under",
+    ExitType::FlowOver => "flow_over",
+  }
+}
+
+pub fn value(prop: &Prop) -> String {
+  match prop {
+    Prop::ExitIf(v) => bool_val(*v).into(),
+    Prop::Transition(v) => transition_val(*v).into(),
+    Prop::Name(v) => word(v),
+    Prop::Sensor(v) => sensor_val(*v).into(),
+    Prop::Pump(v) => pump_val(*v).into(),
+    Prop::ExitType(v) => exit_type_val(*v).into(),
+    Prop::Flow(v)
+    | Prop::Volume(v)
+    | Prop::MaxFlowOrPressureRange(v)
+    | Prop::ExitFlowUnder(v)
+    | Prop::Temperature(v)
+    | Prop::Pressure(v)
+    | Prop::ExitFlowOver(v)
+    | Prop::ExitPressureOver(v)
+    | Prop::MaxFlowOrPressure(v)
+    | Prop::ExitPressureUnder(v)
+    | Prop::Seconds(v)
+    | Prop::Weight(v) => number(*v),
+    Prop::Unknown((_, v)) => word(v),
+  }
+}
+
+pub fn prop(prop: &Prop) -> String {
+  format!("{} {}", prop.name(), value(prop))
+}
+
+pub fn step(step: &Step) -> String {
+  let props = step.0.iter().map(prop).collect::<Vec<String>>();
+  format!("{{{}}}", props.join(" "))
+}
+
+pub fn steps(steps: &[Step]) -> String {
+  steps.iter().map(step).collect::<Vec<String>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::parse;
+  use crate::profile::{fixture_profiles, Preset, Source};
+
+  fn round_trip(tcl: &str) {
+    let list = parse(tcl).expect("should be parsed");
+    assert_eq!(parse(&steps(&list)), Ok(list));
+  }
+
+  #[test]
+  fn test_word() {
+    assert_eq!(word("Fill"), "Fill");
+    assert_eq!(word(""), "{}");
+    assert_eq!(word("Pressure Up"), "{Pressure Up}");
+    assert_eq!(word("New\nLine"), "{New\nLine}");
+    assert_eq!(word("#1"), "{#1}");
+    assert_eq!(word("a}b"), "a\\}b");
+    assert_eq!(word("C:\\ 1"), "C:\\\\\\ 1");
+  }
+
+  #[test]
+  fn test_number() {
+    assert_eq!(number(94.0), "94");
+    assert_eq!(number(0.6), "0.6");
+    assert_eq!(number(1.5), "1.5");
+    assert_eq!(number(70000.0), "70000");
+  }
+
+  #[test]
+  fn test_step() {
+    assert_eq!(
+      step(&Step(vec![
+        Prop::ExitIf(true),
+        Prop::Name("Pressure Up".into()),
+        Prop::Transition(TransitionType::Smooth),
+        Prop::Temperature(93.5),
+        Prop::Unknown(("popup".into(), "Stir now".into())),
+      ])),
+      "{exit_if 1 name {Pressure Up} transition smooth temperature 93.5 popup {Stir now}}"
+    );
+  }
+
+  #[test]
+  fn test_round_trip_words() {
+    for name in &[
+      "Bloom {long} soak",
+      "a}b{",
+      "say \"hi\"",
+      "C:\\temp",
+      "tab\tand\nnewline",
+      "$5 [cheap]",
+      "#1",
+      "",
+    ] {
+      let list = vec![Step(vec![Prop::Name(name.to_string())])];
+      assert_eq!(parse(&steps(&list)), Ok(list));
+    }
+  }
+
+  #[test]
+  fn test_round_trip_fixtures() {
+    round_trip(include_str!("../fixtures/step.outer"));
+    round_trip(include_str!("../fixtures/steps.inner"));
+    round_trip(include_str!("../fixtures/steps.inner.xxx"));
+  }
+
+  #[test]
+  fn test_round_trip_presets() {
+    for text in fixture_profiles() {
+      let preset = Preset::load(text, Source::Bundled).unwrap();
+      assert!(!preset.steps.is_empty(), "{}", preset.title);
+      round_trip(&preset.data);
+    }
+  }
+}
@@ -0,0 +1,255 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::parser::{ExitType, Prop, PumpType, SensorType, Step, TransitionType};
+
+// No step, and no simulated shot, runs longer than an hour, so the loops
+// that walk a shot tick by tick stay bounded.
+pub const MAX_SECONDS: f64 = 3600.;
+
+// A step with one field per property the DE1 understands. Absent properties
+// take the values the DE1 app falls back to when it packs a shot frame:
+// numbers are 0, except the limiter range which starts at the app's 0.6;
+// `pump`, `sensor` and `transition` are only checked against `flow`,
+// `water` and `smooth`, so anything else means pressure, coffee and fast.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdvancedStep {
+  pub name: String,
+  pub temperature: f64,
+  pub sensor: SensorType,
+  pub pump: PumpType,
+  pub transition: TransitionType,
+  pub pressure: f64,
+  pub flow: f64,
+  pub seconds: f64,
+  pub volume: f64,
+  pub weight: f64,
+  pub exit_if: bool,
+  pub exit_type: Option<ExitType>,
+  pub exit_pressure_over: f64,
+  pub exit_pressure_under: f64,
+  pub exit_flow_over: f64,
+  pub exit_flow_under: f64,
+  pub max_flow_or_pressure: f64,
+  pub max_flow_or_pressure_range: f64,
+  pub unknown: Vec<(String, String)>,
+}
+
+impl Default for AdvancedStep {
+  fn default() -> Self {
+    Self {
+      name: "".into(),
+      temperature: 0.,
+      sensor: SensorType::Coffee,
+      pump: PumpType::Pressure,
+      transition: TransitionType::Fast,
+      pressure: 0.,
+      flow: 0.,
+      seconds: 0.,
+      volume: 0.,
+      weight: 0.,
+      exit_if: false,
+      exit_type: None,
+      exit_pressure_over: 0.,
+      exit_pressure_under: 0.,
+      exit_flow_over: 0.,
+      exit_flow_under: 0.,
+      max_flow_or_pressure: 0.,
+      max_flow_or_pressure_range: 0.6,
+      unknown: vec![],
+    }
+  }
+}
+
+impl AdvancedStep {
+  // The exit condition that is actually armed, with its threshold.
+  pub fn exit(&self) -> Option<(ExitType, f64)> {
+    if !self.exit_if {
+      return None;
+    }
+    self.exit_type.map(|exit_type| {
+      let v = match exit_type {
+        ExitType::PressureOver => self.exit_pressure_over,
+        ExitType::PressureUnder => self.exit_pressure_under,
+        ExitType::FlowOver => self.exit_flow_over,
+        ExitType::FlowUnder => self.exit_flow_under,
+      };
+      (exit_type, v)
+    })
+  }
+
+  // The setpoint of the series the pump controls in this step.
+  pub fn target(&self) -> f64 {
+    match self.pump {
+      PumpType::Pressure => self.pressure,
+      PumpType::Flow => self.flow,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepErrorKind {
+  Duplicate,
+  Negative,
+  TooLong,
+}
+
+// `index` points at the offending property in `Step.0`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StepError {
+  pub index: usize,
+  pub kind: StepErrorKind,
+}
+
+impl StepError {
+  pub fn expected(&self) -> &'static str {
+    match self.kind {
+      StepErrorKind::Duplicate => "each property only once per step",
+      StepErrorKind::Negative => "a value of 0 or more",
+      StepErrorKind::TooLong => "at most 3600 seconds",
+    }
+  }
+}
+
+impl fmt::Display for StepError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "property {}: expected {}", self.index + 1, self.expected())
+  }
+}
+
+impl TryFrom<&Step> for AdvancedStep {
+  type Error = StepError;
+
+  fn try_from(step: &Step) -> Result<Self, Self::Error> {
+    let mut ret = AdvancedStep::default();
+    for (index, prop) in step.0.iter().enumerate() {
+      let err = |kind| StepError { index, kind };
+      if step.0[..index].iter().any(|p| p.name() == prop.name()) {
+        return Err(err(StepErrorKind::Duplicate));
+      }
+
+      match prop {
+        Prop::Seconds(v) | Prop::Pressure(v) | Prop::Flow(v) | Prop::Temperature(v)
+          if *v < 0. =>
+        {
+          return Err(err(StepErrorKind::Negative))
+        }
+        Prop::Seconds(v) if *v > MAX_SECONDS => return Err(err(StepErrorKind::TooLong)),
+        _ => (),
+      }
+
+      match prop {
+        Prop::ExitIf(v) => ret.exit_if = *v,
+        Prop::Flow(v) => ret.flow = *v,
+        Prop::Volume(v) => ret.volume = *v,
+        Prop::MaxFlowOrPressureRange(v) => ret.max_flow_or_pressure_range = *v,
+        Prop::Transition(v) => ret.transition = *v,
+        Prop::ExitFlowUnder(v) => ret.exit_flow_under = *v,
+        Prop::Temperature(v) => ret.temperature = *v,
+        Prop::Name(v) => ret.name = v.clone(),
+        Prop::Pressure(v) => ret.pressure = *v,
+        Prop::Sensor(v) => ret.sensor = *v,
+        Prop::Pump(v) => ret.pump = *v,
+        Prop::ExitType(v) => ret.exit_type = Some(*v),
+        Prop::ExitFlowOver(v) => ret.exit_flow_over = *v,
+        Prop::ExitPressureOver(v) => ret.exit_pressure_over = *v,
+        Prop::MaxFlowOrPressure(v) => ret.max_flow_or_pressure = *v,
+        Prop::ExitPressureUnder(v) => ret.exit_pressure_under = *v,
+        Prop::Seconds(v) => ret.seconds = *v,
+        Prop::Weight(v) => ret.weight = *v,
+        Prop::Unknown((name, v)) => ret.unknown.push((name.clone(), v.clone())),
+      }
+    }
+    Ok(ret)
+  }
+}
+
+// The parser already reports and drops the properties that keep a step from
+// converting, so this only skips steps built by hand.
+pub fn advanced_steps(steps: &[Step]) -> Vec<AdvancedStep> {
+  steps
+    .iter()
+    .filter_map(|step| AdvancedStep::try_from(step).ok())
+    .collect()
+}
+
+// A fast step at 90 °C that holds `v` on the series `pump` controls, for
+// the tests of the modules that chart and simulate steps.
+#[cfg(test)]
+pub fn target_step(pump: PumpType, v: f64, seconds: f64) -> AdvancedStep {
+  AdvancedStep {
+    temperature: 90.,
+    pump,
+    pressure: if pump == PumpType::Pressure { v } else { 0. },
+    flow: if pump == PumpType::Flow { v } else { 0. },
+    seconds,
+    ..AdvancedStep::default()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_defaults() {
+    assert_eq!(
+      AdvancedStep::try_from(&Step(vec![Prop::Seconds(4.0)])),
+      Ok(AdvancedStep {
+        seconds: 4.0,
+        ..AdvancedStep::default()
+      })
+    );
+  }
+
+  #[test]
+  fn test_try_from() {
+    let step = Step(vec![
+      Prop::ExitIf(true),
+      Prop::Flow(8.0),
+      Prop::Name("Fill".into()),
+      Prop::Pump(PumpType::Flow),
+      Prop::ExitType(ExitType::PressureOver),
+      Prop::ExitPressureOver(1.5),
+      Prop::Seconds(25.0),
+      Prop::Unknown(("popup".into(), "Stir".into())),
+    ]);
+    let step = AdvancedStep::try_from(&step).unwrap();
+    assert_eq!(step.name, "Fill");
+    assert_eq!(step.pump, PumpType::Flow);
+    assert_eq!(step.target(), 8.0);
+    assert_eq!(step.exit(), Some((ExitType::PressureOver, 1.5)));
+    assert_eq!(step.unknown, vec![("popup".into(), "Stir".into())]);
+  }
+
+  #[test]
+  fn test_try_from_errors() {
+    assert_eq!(
+      AdvancedStep::try_from(&Step(vec![
+        Prop::Seconds(4.0),
+        Prop::Flow(2.0),
+        Prop::Seconds(5.0),
+      ])),
+      Err(StepError {
+        index: 2,
+        kind: StepErrorKind::Duplicate
+      })
+    );
+    assert_eq!(
+      AdvancedStep::try_from(&Step(vec![Prop::Flow(-2.0)])),
+      Err(StepError {
+        index: 0,
+        kind: StepErrorKind::Negative
+      })
+    );
+    assert_eq!(
+      AdvancedStep::try_from(&Step(vec![Prop::Flow(2.0), Prop::Seconds(1e6)])),
+      Err(StepError {
+        index: 1,
+        kind: StepErrorKind::TooLong
+      })
+    );
+    assert!(AdvancedStep::try_from(&Step(vec![Prop::Seconds(MAX_SECONDS)])).is_ok());
+    assert!(AdvancedStep::try_from(&Step(vec![Prop::ExitPressureUnder(-1.0)])).is_ok());
+  }
+}
@@ -0,0 +1,113 @@
+use seed::prelude::{LocalStorage, WebStorage};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::utils::console_log;
+
+const KEY: &str = "profile-viewer";
+// Imported profiles change rarely but are big, so they are stored apart
+// from the editor, which is saved on every keystroke.
+const PRESETS_KEY: &str = "profile-viewer-presets";
+// Bump when `Saved` or `SavedPresets` change and teach `migrate` how to read
+// the old layout.
+const VERSION: u64 = 1;
+
+// What survives a reload of the editor. The document is kept as `.tcl` text
+// so it loads the same way as a picked file.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Saved {
+  pub version: u64,
+  pub text: String,
+  pub document: String,
+  pub selected: Option<String>,
+}
+
+// The imported profiles as `.tcl` text, by key.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct SavedPresets {
+  version: u64,
+  user_profiles: Vec<(String, String)>,
+}
+
+// Brings stored data of any known version up to `VERSION`; `None` when it
+// can't be read, such as data from a newer version.
+fn migrate<T: DeserializeOwned>(value: Value) -> Option<T> {
+  match value.get("version")?.as_u64()? {
+    VERSION => serde_json::from_value(value).ok(),
+    _ => None,
+  }
+}
+
+pub fn load() -> Option<Saved> {
+  let value: Value = LocalStorage::get(KEY).ok()?;
+  let saved = migrate(value);
+  if saved.is_none() {
+    console_log("stored state ignored: unknown version".into());
+  }
+  saved
+}
+
+pub fn load_presets() -> Vec<(String, String)> {
+  let value: Value = match LocalStorage::get(PRESETS_KEY) {
+    Ok(value) => value,
+    Err(_) => return vec![],
+  };
+  match migrate::<SavedPresets>(value) {
+    Some(saved) => saved.user_profiles,
+    None => {
+      console_log("stored profiles ignored: unknown version".into());
+      vec![]
+    }
+  }
+}
+
+pub fn save(saved: Saved) {
+  let saved = Saved {
+    version: VERSION,
+    ..saved
+  };
+  if let Err(error) = LocalStorage::insert(KEY, &saved) {
+    console_log(format!("state not stored: {:?}", error));
+  }
+}
+
+pub fn save_presets(user_profiles: Vec<(String, String)>) {
+  let saved = SavedPresets {
+    version: VERSION,
+    user_profiles,
+  };
+  if let Err(error) = LocalStorage::insert(PRESETS_KEY, &saved) {
+    console_log(format!("profiles not stored: {:?}", error));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn test_migrate() {
+    let saved = Saved {
+      version: VERSION,
+      text: "{flow 2}".into(),
+      document: "profile_title Test\n".into(),
+      selected: Some("user/test.tcl".into()),
+    };
+    let value = serde_json::to_value(&saved).unwrap();
+    assert_eq!(migrate(value), Some(saved));
+
+    let presets = SavedPresets {
+      version: VERSION,
+      user_profiles: vec![("user/test.tcl".into(), "profile_title Test\n".into())],
+    };
+    let value = serde_json::to_value(&presets).unwrap();
+    assert_eq!(migrate(value), Some(presets));
+
+    let newer = json!({ "version": VERSION + 1, "text": "" });
+    assert_eq!(migrate::<Saved>(newer), None);
+    assert_eq!(migrate::<Saved>(json!({ "text": "" })), None);
+    assert_eq!(migrate::<SavedPresets>(json!("text")), None);
+  }
+}
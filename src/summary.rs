@@ -0,0 +1,161 @@
+use crate::classify::{classify, Style};
+use crate::estimate::estimate;
+use crate::parser::{ExitType, PumpType};
+use crate::profile::{ExitReason, PuckModel};
+use crate::step::AdvancedStep;
+use crate::timeline::{Segment, Timeline};
+
+// Pressure above which the shot counts as past preinfusion, in bar.
+const PREINFUSION_PRESSURE: f64 = 4.;
+
+// Figures about a whole profile, read off its targets with every step
+// running its full `seconds`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+  pub total_time: f64,
+  // seconds before the pressure target first goes over 4 bar, `None` if it
+  // never does
+  pub preinfusion: Option<f64>,
+  pub peak_pressure: f64,
+  pub peak_flow: f64,
+  // `(min, max)` in °C
+  pub temperature: Option<(f64, f64)>,
+  pub pressure_steps: usize,
+  pub flow_steps: usize,
+  // distinct, in order of first use
+  pub exits: Vec<ExitReason>,
+  // ml pumped, estimated with the puck model
+  pub volume: f64,
+  pub styles: Vec<Style>,
+}
+
+fn peak(list: &[Segment]) -> f64 {
+  list
+    .iter()
+    .flat_map(|s| vec![s.start.1, s.end.1])
+    .fold(0., f64::max)
+}
+
+fn exits(step: &AdvancedStep) -> Vec<ExitReason> {
+  let mut reasons = vec![];
+  if let Some((exit_type, _)) = step.exit() {
+    reasons.push(match exit_type {
+      ExitType::PressureOver => ExitReason::PressureOver,
+      ExitType::PressureUnder => ExitReason::PressureUnder,
+      ExitType::FlowOver => ExitReason::FlowOver,
+      ExitType::FlowUnder => ExitReason::FlowUnder,
+    });
+  }
+  if step.volume > 0. {
+    reasons.push(ExitReason::Volume);
+  }
+  if step.weight > 0. {
+    reasons.push(ExitReason::Weight);
+  }
+  reasons
+}
+
+pub fn summary(steps: &[AdvancedStep], puck: &PuckModel) -> Summary {
+  let timeline = Timeline::new(steps);
+  let volume = estimate(steps, &timeline, puck, None)
+    .points
+    .last()
+    .map_or(0., |point| point.volume);
+
+  let preinfusion = timeline
+    .pressure
+    .iter()
+    .find(|s| s.end.1 > PREINFUSION_PRESSURE)
+    .map(|s| timeline.spans[s.step].0);
+
+  let temperature =
+    steps
+      .iter()
+      .map(|step| step.temperature)
+      .fold(None, |range: Option<(f64, f64)>, t| match range {
+        Some((min, max)) => Some((min.min(t), max.max(t))),
+        None => Some((t, t)),
+      });
+
+  let mut reasons = vec![];
+  for reason in steps.iter().flat_map(exits) {
+    if !reasons.contains(&reason) {
+      reasons.push(reason);
+    }
+  }
+
+  let count = |pump| steps.iter().filter(|step| step.pump == pump).count();
+  Summary {
+    total_time: timeline.duration,
+    preinfusion,
+    peak_pressure: peak(&timeline.pressure),
+    peak_flow: peak(&timeline.flow),
+    temperature,
+    pressure_steps: count(PumpType::Pressure),
+    flow_steps: count(PumpType::Flow),
+    exits: reasons,
+    volume,
+    styles: classify(steps),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::step::target_step;
+
+  #[test]
+  fn test_summary() {
+    let fill = AdvancedStep {
+      exit_if: true,
+      exit_type: Some(ExitType::PressureOver),
+      exit_pressure_over: 3.,
+      temperature: 88.,
+      ..target_step(PumpType::Flow, 4., 10.)
+    };
+    let soak = AdvancedStep {
+      volume: 20.,
+      ..target_step(PumpType::Pressure, 3., 5.)
+    };
+    let pour = AdvancedStep {
+      weight: 36.,
+      temperature: 93.,
+      ..target_step(PumpType::Pressure, 9., 20.)
+    };
+    let summary = summary(&[fill, soak, pour], &PuckModel::default());
+    assert_eq!(summary.total_time, 35.);
+    assert_eq!(summary.preinfusion, Some(15.));
+    assert_eq!(summary.peak_pressure, 9.);
+    assert_eq!(summary.peak_flow, 4.);
+    assert_eq!(summary.temperature, Some((88., 93.)));
+    assert_eq!((summary.pressure_steps, summary.flow_steps), (2, 1));
+    assert_eq!(
+      summary.exits,
+      vec![
+        ExitReason::PressureOver,
+        ExitReason::Volume,
+        ExitReason::Weight
+      ]
+    );
+    assert!(summary.volume > 40.);
+    assert!(summary.styles.is_empty());
+  }
+
+  #[test]
+  fn test_summary_empty() {
+    let summary = summary(
+      &[target_step(PumpType::Flow, 2., 10.)],
+      &PuckModel::default(),
+    );
+    assert_eq!(summary.preinfusion, None);
+    assert_eq!(summary.peak_pressure, 0.);
+    assert!(summary.exits.is_empty());
+    assert!((summary.volume - 20.).abs() < 1e-6);
+    assert_eq!(summary.styles, vec![Style::FlowProfiled]);
+
+    assert_eq!(
+      super::summary(&[], &PuckModel::default()),
+      Summary::default()
+    );
+  }
+}
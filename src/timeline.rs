@@ -0,0 +1,561 @@
+use crate::parser::{ExitType, PumpType, SensorType, TransitionType};
+use crate::step::{AdvancedStep, MAX_SECONDS};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Series {
+  Temperature,
+  Pressure,
+  Flow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SegmentKind {
+  // an instant change at the start of a step
+  Jump,
+  // a smooth transition over the whole step
+  Ramp,
+  // the target held for the rest of the step
+  Hold,
+}
+
+// A straight line from `start` to `end`, both `(seconds, value)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Segment {
+  pub step: usize,
+  pub kind: SegmentKind,
+  pub pump: PumpType,
+  // where the step measures its temperature
+  pub sensor: SensorType,
+  pub start: (f64, f64),
+  pub end: (f64, f64),
+}
+
+impl Segment {
+  fn value_at(&self, t: f64) -> f64 {
+    let ((x1, y1), (x2, y2)) = (self.start, self.end);
+    if x2 == x1 {
+      return y2;
+    }
+    y1 + (y2 - y1) * (t - x1) / (x2 - x1)
+  }
+}
+
+// How the basket lags behind water-sensor steps, which set the temperature
+// of the water mixed at the group rather than of the coffee bed: the basket
+// approaches `loss` °C below the water with `time_constant` in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThermalLag {
+  pub time_constant: f64,
+  pub loss: f64,
+}
+
+impl Default for ThermalLag {
+  fn default() -> Self {
+    Self {
+      time_constant: 4.,
+      loss: 2.,
+    }
+  }
+}
+
+// A step's limiter: the pump starts backing off `range` below `value` on
+// the series it doesn't control, and holds it at `value`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limit {
+  pub step: usize,
+  pub series: Series,
+  pub start: f64,
+  pub end: f64,
+  pub value: f64,
+  pub range: f64,
+}
+
+impl Limit {
+  // `(low, high)` of the band the limiter acts in.
+  pub fn band(&self) -> (f64, f64) {
+    ((self.value - self.range).max(0.), self.value)
+  }
+}
+
+// Sampling interval of derived curves in seconds.
+const TICK: f64 = 0.1;
+
+// The target curves of a step list over time, assuming every step runs its
+// full `seconds`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Timeline {
+  pub temperature: Vec<Segment>,
+  pub pressure: Vec<Segment>,
+  pub flow: Vec<Segment>,
+  pub limits: Vec<Limit>,
+  // `(start, end)` of each step
+  pub spans: Vec<(f64, f64)>,
+  pub duration: f64,
+}
+
+// Where the firmware starts a step from: the pressure and flow measured when
+// the previous step ended. A step's own series ends at its target, unless its
+// exit fired on the way there; an exit on the other series fires at its
+// threshold. Otherwise the series it doesn't control keeps the last value
+// known for it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Carried {
+  pressure: f64,
+  flow: f64,
+}
+
+impl Carried {
+  fn get(&self, pump: PumpType) -> f64 {
+    match pump {
+      PumpType::Pressure => self.pressure,
+      PumpType::Flow => self.flow,
+    }
+  }
+
+  fn set(&mut self, pump: PumpType, v: f64) {
+    match pump {
+      PumpType::Pressure => self.pressure = v,
+      PumpType::Flow => self.flow = v,
+    }
+  }
+
+  fn after(self, step: &AdvancedStep) -> Self {
+    let target = step.target();
+    // a fast step is at its target right from the start
+    let from = match step.transition {
+      TransitionType::Fast => target,
+      TransitionType::Smooth => self.get(step.pump),
+    };
+    let mut carried = self;
+    carried.set(step.pump, target);
+    if let Some((exit_type, v)) = step.exit() {
+      let series = match exit_type {
+        ExitType::PressureOver | ExitType::PressureUnder => PumpType::Pressure,
+        ExitType::FlowOver | ExitType::FlowUnder => PumpType::Flow,
+      };
+      if series != step.pump || (from.min(target) <= v && v <= from.max(target)) {
+        carried.set(series, v);
+      }
+    }
+    carried
+  }
+}
+
+impl Timeline {
+  // Follows the firmware's transitions: a fast step jumps to its targets, a
+  // smooth one ramps temperature and its pump series from where the previous
+  // step left them over its whole `seconds`. The series the pump doesn't
+  // control isn't drawn.
+  pub fn new(steps: &[AdvancedStep]) -> Self {
+    let mut timeline = Timeline::default();
+    let mut elapsed_time = 0f64;
+    let mut carried = Carried::default();
+
+    for (index, step) in steps.iter().enumerate() {
+      let duration = step.seconds;
+      let pump = step.pump;
+      let end = elapsed_time + duration;
+      let sensor = step.sensor;
+      let segment = |kind, start, end| Segment {
+        step: index,
+        kind,
+        pump,
+        sensor,
+        start,
+        end,
+      };
+      let transition = |list: &mut Vec<Segment>, from: f64, to: f64| match step.transition {
+        TransitionType::Fast => {
+          list.push(segment(
+            SegmentKind::Jump,
+            (elapsed_time, from),
+            (elapsed_time, to),
+          ));
+          list.push(segment(SegmentKind::Hold, (elapsed_time, to), (end, to)));
+        }
+        TransitionType::Smooth => {
+          list.push(segment(SegmentKind::Ramp, (elapsed_time, from), (end, to)));
+        }
+      };
+
+      let t = step.temperature;
+      match timeline.temperature.last().map(|prev| prev.end.1) {
+        Some(prev_t) => transition(&mut timeline.temperature, prev_t, t),
+        None => timeline
+          .temperature
+          .push(segment(SegmentKind::Hold, (elapsed_time, t), (end, t))),
+      }
+
+      let list = match pump {
+        PumpType::Pressure => &mut timeline.pressure,
+        PumpType::Flow => &mut timeline.flow,
+      };
+      let from = carried.get(pump);
+      // keep the line connected when an exit cut the previous step short
+      if let Some(&Segment { end: (px, py), .. }) = list.last() {
+        if px == elapsed_time && py != from {
+          list.push(segment(
+            SegmentKind::Jump,
+            (elapsed_time, py),
+            (elapsed_time, from),
+          ));
+        }
+      }
+      transition(list, from, step.target());
+
+      if step.max_flow_or_pressure > 0. {
+        timeline.limits.push(Limit {
+          step: index,
+          series: match pump {
+            PumpType::Pressure => Series::Flow,
+            PumpType::Flow => Series::Pressure,
+          },
+          start: elapsed_time,
+          end,
+          value: step.max_flow_or_pressure,
+          range: step.max_flow_or_pressure_range,
+        });
+      }
+
+      timeline.spans.push((elapsed_time, end));
+      elapsed_time = end;
+      carried = carried.after(step);
+    }
+
+    timeline.duration = elapsed_time;
+    timeline
+  }
+
+  pub fn series(&self, series: Series) -> &[Segment] {
+    match series {
+      Series::Temperature => &self.temperature,
+      Series::Pressure => &self.pressure,
+      Series::Flow => &self.flow,
+    }
+  }
+
+  // The target at `t`, or `None` while the series isn't driven. At a step
+  // boundary the new step's value wins.
+  pub fn value_at(&self, series: Series, t: f64) -> Option<f64> {
+    let list = self.series(series);
+    list
+      .iter()
+      .rev()
+      .find(|s| s.start.0 < s.end.0 && s.start.0 <= t && t < s.end.0)
+      .or_else(|| {
+        list
+          .iter()
+          .rev()
+          .find(|s| s.start.0 < s.end.0 && t == s.end.0 && t == self.duration)
+      })
+      .map(|s| s.value_at(t))
+  }
+
+  // The estimated basket temperature as `(seconds, °C)`, sampled every
+  // `TICK` for at most `MAX_SECONDS`. Coffee-sensor steps hold the basket at
+  // their target; during water-sensor steps it trails the water by `lag`.
+  pub fn basket_temperature(&self, lag: &ThermalLag) -> Vec<(f64, f64)> {
+    let mut points = vec![];
+    let mut basket = match self.temperature.first() {
+      Some(segment) => segment.start.1,
+      None => return points,
+    };
+    let duration = self.duration.min(MAX_SECONDS);
+    let mut time = 0f64;
+    points.push((time, basket));
+    while time < duration {
+      let tick = TICK.min(duration - time);
+      time += tick;
+      let step = match self.step_at(time - tick / 2.) {
+        Some(step) => step,
+        None => continue,
+      };
+      let water = self
+        .value_at(Series::Temperature, time - tick / 2.)
+        .unwrap_or(basket);
+      let sensor = self
+        .temperature
+        .iter()
+        .find(|segment| segment.step == step)
+        .map_or(SensorType::Coffee, |segment| segment.sensor);
+      basket = match sensor {
+        SensorType::Coffee => water,
+        SensorType::Water if lag.time_constant > 0. => {
+          let target = water - lag.loss;
+          target + (basket - target) * (-tick / lag.time_constant).exp()
+        }
+        SensorType::Water => water - lag.loss,
+      };
+      points.push((time, basket));
+    }
+    points
+  }
+
+  // The step running at `t`; the last step owns the very end of the shot.
+  pub fn step_at(&self, t: f64) -> Option<usize> {
+    self
+      .spans
+      .iter()
+      .position(|&(start, end)| start <= t && t < end)
+      .or_else(|| {
+        self
+          .spans
+          .iter()
+          .rposition(|&(start, end)| start < end && t == end && t == self.duration)
+      })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::steps;
+  use crate::step::{advanced_steps, target_step};
+
+  fn smooth(pump: PumpType, v: f64, seconds: f64) -> AdvancedStep {
+    AdvancedStep {
+      transition: TransitionType::Smooth,
+      ..target_step(pump, v, seconds)
+    }
+  }
+
+  fn kinds(list: &[Segment]) -> Vec<(usize, SegmentKind)> {
+    list.iter().map(|s| (s.step, s.kind)).collect()
+  }
+
+  #[test]
+  fn test_segments() {
+    let timeline = Timeline::new(&[
+      target_step(PumpType::Flow, 4., 10.),
+      target_step(PumpType::Pressure, 9., 10.),
+      smooth(PumpType::Pressure, 6., 20.),
+    ]);
+    assert_eq!(timeline.duration, 40.);
+    assert_eq!(timeline.spans, vec![(0., 10.), (10., 20.), (20., 40.)]);
+    assert_eq!(
+      kinds(&timeline.flow),
+      vec![(0, SegmentKind::Jump), (0, SegmentKind::Hold),]
+    );
+    assert_eq!(
+      kinds(&timeline.pressure),
+      vec![
+        (1, SegmentKind::Jump),
+        (1, SegmentKind::Hold),
+        (2, SegmentKind::Ramp),
+      ]
+    );
+    assert_eq!(timeline.pressure[2].start, (20., 9.));
+    assert_eq!(timeline.pressure[2].end, (40., 6.));
+    assert_eq!(timeline.pressure[2].pump, PumpType::Pressure);
+    assert_eq!(
+      kinds(&timeline.temperature),
+      vec![
+        (0, SegmentKind::Hold),
+        (1, SegmentKind::Jump),
+        (1, SegmentKind::Hold),
+        (2, SegmentKind::Ramp),
+      ]
+    );
+    assert_eq!(timeline.temperature[3].start, (20., 90.));
+  }
+
+  fn fixture(tcl: &str) -> Timeline {
+    let (_, parsed) = steps(tcl.as_bytes()).unwrap();
+    Timeline::new(&advanced_steps(&parsed))
+  }
+
+  type Line = (usize, SegmentKind, (f64, f64), (f64, f64));
+
+  fn lines(list: &[Segment]) -> Vec<Line> {
+    list
+      .iter()
+      .map(|s| (s.step, s.kind, s.start, s.end))
+      .collect()
+  }
+
+  #[test]
+  fn test_fixture_steps() {
+    use SegmentKind::*;
+    let timeline = fixture(include_str!("../fixtures/steps.inner"));
+    assert_eq!(
+      lines(&timeline.temperature),
+      vec![
+        (0, Hold, (0., 94.), (25., 94.)),
+        (1, Jump, (25., 94.), (25., 93.)),
+        (1, Hold, (25., 93.), (29., 93.)),
+      ]
+    );
+    // Fill jumps straight to 2 bar, so its exit over 1.5 bar says nothing
+    // about where Pressure Up starts
+    assert_eq!(
+      lines(&timeline.pressure),
+      vec![
+        (0, Jump, (0., 0.), (0., 2.)),
+        (0, Hold, (0., 2.), (25., 2.)),
+        (1, Jump, (25., 2.), (25., 9.)),
+        (1, Hold, (25., 9.), (29., 9.)),
+      ]
+    );
+    assert_eq!(lines(&timeline.flow), vec![]);
+  }
+
+  #[test]
+  fn test_fixture_transitions() {
+    use SegmentKind::*;
+    let timeline = fixture(include_str!("../fixtures/steps.transitions"));
+    assert_eq!(
+      timeline.spans,
+      vec![(0., 10.), (10., 15.), (15., 35.), (35., 45.)]
+    );
+    assert_eq!(
+      lines(&timeline.temperature),
+      vec![
+        (0, Hold, (0., 88.), (10., 88.)),
+        (1, Ramp, (10., 88.), (15., 92.)),
+        (2, Ramp, (15., 92.), (35., 90.)),
+        (3, Ramp, (35., 90.), (45., 90.)),
+      ]
+    );
+    // the rise starts from the pressure preinfusion exited at
+    assert_eq!(
+      lines(&timeline.pressure),
+      vec![
+        (1, Ramp, (10., 3.), (15., 9.)),
+        (2, Ramp, (15., 9.), (35., 6.)),
+      ]
+    );
+    // flow picks up from the last value known for it, not from 0
+    assert_eq!(
+      lines(&timeline.flow),
+      vec![
+        (0, Jump, (0., 0.), (0., 4.)),
+        (0, Hold, (0., 4.), (10., 4.)),
+        (3, Ramp, (35., 4.), (45., 2.)),
+      ]
+    );
+    assert_eq!(timeline.value_at(Series::Temperature, 12.5), Some(90.));
+    assert_eq!(timeline.value_at(Series::Flow, 20.), None);
+  }
+
+  #[test]
+  fn test_exit_flow() {
+    let exit_over = |transition| AdvancedStep {
+      exit_if: true,
+      exit_type: Some(ExitType::FlowOver),
+      exit_flow_over: 3.,
+      transition,
+      ..target_step(PumpType::Flow, 4., 10.)
+    };
+    let next = smooth(PumpType::Flow, 2., 10.);
+
+    // the ramp passes 3 ml/s, so the next step starts from there
+    let timeline = Timeline::new(&[exit_over(TransitionType::Smooth), next.clone()]);
+    assert_eq!(timeline.flow[1].start, (10., 4.));
+    assert_eq!(timeline.flow[1].end, (10., 3.));
+    assert_eq!(timeline.flow[2].kind, SegmentKind::Ramp);
+    assert_eq!(timeline.flow[2].start, (10., 3.));
+
+    // a fast step is at 4 ml/s from the start
+    let timeline = Timeline::new(&[exit_over(TransitionType::Fast), next]);
+    assert_eq!(timeline.flow[2].kind, SegmentKind::Ramp);
+    assert_eq!(timeline.flow[2].start, (10., 4.));
+  }
+
+  #[test]
+  fn test_limits() {
+    let limited = |pump, v| AdvancedStep {
+      max_flow_or_pressure: v,
+      max_flow_or_pressure_range: 0.6,
+      ..target_step(pump, 4., 10.)
+    };
+    let timeline = Timeline::new(&[
+      limited(PumpType::Flow, 9.),
+      target_step(PumpType::Flow, 4., 10.),
+      limited(PumpType::Pressure, 0.4),
+    ]);
+    assert_eq!(
+      timeline.limits,
+      vec![
+        Limit {
+          step: 0,
+          series: Series::Pressure,
+          start: 0.,
+          end: 10.,
+          value: 9.,
+          range: 0.6,
+        },
+        Limit {
+          step: 2,
+          series: Series::Flow,
+          start: 20.,
+          end: 30.,
+          value: 0.4,
+          range: 0.6,
+        },
+      ]
+    );
+    assert_eq!(timeline.limits[1].band(), (0., 0.4));
+  }
+
+  #[test]
+  fn test_basket_temperature() {
+    let water = AdvancedStep {
+      sensor: SensorType::Water,
+      temperature: 94.,
+      ..target_step(PumpType::Flow, 4., 10.)
+    };
+    let timeline = Timeline::new(&[target_step(PumpType::Flow, 4., 10.), water]);
+    assert_eq!(timeline.temperature[2].sensor, SensorType::Water);
+
+    let lag = ThermalLag {
+      time_constant: 2.,
+      loss: 1.,
+    };
+    let points = timeline.basket_temperature(&lag);
+    assert_eq!(points.len(), 201);
+    let at = |t: f64| {
+      points
+        .iter()
+        .find(|(time, _)| (time - t).abs() < 1e-6)
+        .unwrap()
+        .1
+    };
+    assert_eq!(at(5.), 90.);
+    // one time constant in, 63% of the way from 90 to 93
+    assert!((at(12.) - (93. - 3. * (-1f64).exp())).abs() < 1e-6);
+    assert!((at(20.) - 93.).abs() < 0.05);
+  }
+
+  #[test]
+  fn test_value_at() {
+    let timeline = Timeline::new(&[
+      target_step(PumpType::Flow, 4., 10.),
+      target_step(PumpType::Pressure, 9., 10.),
+      smooth(PumpType::Pressure, 6., 20.),
+    ]);
+    assert_eq!(timeline.value_at(Series::Flow, 0.), Some(4.));
+    assert_eq!(timeline.value_at(Series::Flow, 5.), Some(4.));
+    assert_eq!(timeline.value_at(Series::Flow, 15.), None);
+    assert_eq!(timeline.value_at(Series::Pressure, 5.), None);
+    assert_eq!(timeline.value_at(Series::Pressure, 10.), Some(9.));
+    assert_eq!(timeline.value_at(Series::Pressure, 30.), Some(7.5));
+    assert_eq!(timeline.value_at(Series::Pressure, 40.), Some(6.));
+    assert_eq!(timeline.value_at(Series::Pressure, 41.), None);
+    assert_eq!(timeline.value_at(Series::Temperature, 12.), Some(90.));
+  }
+
+  #[test]
+  fn test_step_at() {
+    let timeline = Timeline::new(&[
+      target_step(PumpType::Flow, 4., 10.),
+      target_step(PumpType::Flow, 4., 0.),
+      target_step(PumpType::Pressure, 9., 10.),
+    ]);
+    assert_eq!(timeline.step_at(0.), Some(0));
+    assert_eq!(timeline.step_at(9.9), Some(0));
+    assert_eq!(timeline.step_at(10.), Some(2));
+    assert_eq!(timeline.step_at(20.), Some(2));
+    assert_eq!(timeline.step_at(-1.), None);
+    assert_eq!(timeline.step_at(21.), None);
+  }
+}
@@ -1,3 +1,34 @@
+use seed::prelude::{JsCast, JsValue};
+
 pub fn console_log(msg: String) {
   seed::prelude::web_sys::console::log_1(&seed::prelude::JsValue::from_str(msg.as_str()));
 }
+
+// Saves `text` as a file by clicking a temporary link to a blob URL.
+pub fn download(file_name: &str, text: &str) -> Result<(), JsValue> {
+  let parts = js_sys::Array::of1(&JsValue::from_str(text));
+  let mut options = web_sys::BlobPropertyBag::new();
+  options.type_("text/plain");
+  let blob = web_sys::Blob::new_with_str_sequence_and_options(&parts, &options)?;
+  let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+
+  let anchor: web_sys::HtmlAnchorElement = seed::document().create_element("a")?.dyn_into()?;
+  anchor.set_href(&url);
+  anchor.set_download(file_name);
+  anchor.click();
+  web_sys::Url::revoke_object_url(&url)
+}
+
+pub fn files(list: Option<web_sys::FileList>) -> Vec<web_sys::File> {
+  list
+    .map(|list| (0..list.length()).filter_map(|i| list.get(i)).collect())
+    .unwrap_or_default()
+}
+
+// Resolves to `None` when the file can't be read as text.
+pub async fn read_text(file: web_sys::File) -> Option<String> {
+  wasm_bindgen_futures::JsFuture::from(file.text())
+    .await
+    .ok()?
+    .as_string()
+}
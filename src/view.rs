@@ -3,39 +3,268 @@ use seed::prelude::*;
 use seed::*;
 
 use crate::axis::{Axis, Direction};
+use crate::estimate::{estimate, Estimate, Point, StopKind};
 use crate::msg::Msg;
-use crate::parser::Step;
-use crate::profile::{analyze, PositionList};
+use crate::parser::SensorType;
+use crate::profile::{ExitReason, PuckModel, Sample, Simulation};
 use crate::scale::scale;
+use crate::step::AdvancedStep;
+use crate::timeline::{Limit, Segment, Series, ThermalLag, Timeline};
 
-static OUTER: Lazy<(f64, f64)> = Lazy::new(|| (600., 400.));
+// The margin right of `INNER` leaves room for the volume axis labels.
+static OUTER: Lazy<(f64, f64)> = Lazy::new(|| (630., 400.));
 static INNER: Lazy<(f64, f64, f64, f64)> = Lazy::new(|| (30., 20., 580., 370.));
 
-pub fn view_svg(steps: &Vec<Step>) -> Node<Msg> {
-  let (temperature_pos, pressure_pos, flow_pos, elapsed_time) = analyze(steps);
+// `baseline` is drawn dashed underneath for comparison, and the steps listed
+// in `highlight` get a shaded band. Volume and weight, estimated with `puck`,
+// have their own axis on the right. With `lag` the estimated basket
+// temperature is drawn too.
+pub fn view_svg(
+  steps: &[AdvancedStep],
+  baseline: Option<&[AdvancedStep]>,
+  highlight: &[usize],
+  puck: &PuckModel,
+  target_weight: Option<f64>,
+  lag: Option<&ThermalLag>,
+) -> Node<Msg> {
+  let timeline = Timeline::new(steps);
+  let estimate = estimate(steps, &timeline, puck, target_weight);
+  let baseline = baseline.map(Timeline::new);
+  let elapsed_time = match &baseline {
+    Some(baseline) => timeline.duration.max(baseline.duration),
+    None => timeline.duration,
+  };
   svg![
     attrs![
         At::Width => px(OUTER.0),
         At::Height => px(OUTER.1),
         At::ViewBox => format!("0 0 {} {}", OUTER.0, OUTER.1),
     ],
-    view_axis(elapsed_time),
+    view_highlight(&timeline, highlight, elapsed_time),
+    view_axis(elapsed_time, true),
+    baseline.map(|baseline| {
+      g![
+        attrs![
+          At::StrokeDasharray => "4 3",
+          At::Opacity => 0.5,
+        ],
+        view_graphs(&baseline, elapsed_time),
+      ]
+    }),
+    view_graphs(&timeline, elapsed_time),
+    lag.map(|lag| view_basket_temperature(&timeline.basket_temperature(lag), elapsed_time)),
+    view_estimate(&estimate, elapsed_time),
+  ]
+}
+
+fn view_estimate(estimate: &Estimate, elapsed_time: f64) -> Node<Msg> {
+  let max_volume = estimate
+    .points
+    .iter()
+    .map(|point| point.volume)
+    .fold(0., f64::max);
+  // round the axis up to the next 10 ml
+  let top = ((max_volume / 10.).ceil() * 10.).max(10.);
+  let x = scale((0., elapsed_time), (INNER.0, INNER.2));
+  let y = scale((0., top), (INNER.3, INNER.1));
+  // a tick every 10 ml, or a coarser multiple of 10 for big volumes
+  let interval = (top / 100.).ceil() * 10.;
+  let axis = Axis::new(
+    (0., top),
+    (0., INNER.1 - INNER.3),
+    Direction::Vertical,
+    interval,
+  )
+  .right()
+  .title("ml / g");
+  let points = |value: fn(&Point) -> f64| {
+    estimate
+      .points
+      .iter()
+      .map(|point| format!("{},{}", x(point.time), y(value(point))))
+      .collect::<Vec<_>>()
+      .join(" ")
+  };
+
+  g![
     g![
-      view_graph_temperature(&temperature_pos, elapsed_time),
-      view_graph_pressure(&pressure_pos, elapsed_time),
-      view_graph_flow(&flow_pos, elapsed_time),
-    ]
+      attrs![At::Transform => format!("translate({},{})", INNER.2, INNER.3)],
+      axis.render(),
+    ],
+    polyline![attrs![
+      At::Points => points(|point| point.volume),
+      At::Fill => "none",
+      At::Stroke => "gray",
+      At::StrokeWidth => "1px",
+    ]],
+    polyline![attrs![
+      At::Points => points(|point| point.weight),
+      At::Fill => "none",
+      At::Stroke => "saddlebrown",
+      At::StrokeWidth => "1px",
+      At::StrokeDasharray => "3 2",
+    ]],
+    estimate.stops.iter().map(|stop| {
+      let label = match stop.kind {
+        StopKind::Volume => "volume",
+        StopKind::Weight => "weight",
+        StopKind::TargetWeight => "target weight",
+      };
+      g![
+        line_![attrs![
+          At::X1 => x(stop.time),
+          At::Y1 => INNER.1,
+          At::X2 => x(stop.time),
+          At::Y2 => INNER.3,
+          At::Stroke => "saddlebrown",
+          At::StrokeWidth => "1px",
+          At::StrokeDasharray => "2 2",
+        ]],
+        text![
+          attrs![
+            At::X => x(stop.time) + 2.,
+            At::Y => INNER.1 + 20.,
+            At::FontSize => "10px",
+            At::Fill => "saddlebrown",
+          ],
+          label,
+        ],
+      ]
+    }),
+  ]
+}
+
+fn view_graphs(timeline: &Timeline, elapsed_time: f64) -> Node<Msg> {
+  g![
+    view_limits(&timeline.limits, elapsed_time),
+    view_graph_temperature(&timeline.temperature, elapsed_time),
+    view_graph_pressure(&timeline.pressure, elapsed_time),
+    view_graph_flow(&timeline.flow, elapsed_time),
+  ]
+}
+
+// The predicted shot: pressure and flow as the puck model reacts, with a
+// marker where each step ended early.
+pub fn view_simulation(simulation: &Simulation) -> Node<Msg> {
+  let elapsed_time = simulation.duration;
+  let x = scale((0., elapsed_time), (INNER.0, INNER.2));
+  let y = scale((0., 12.), (INNER.3, INNER.1));
+  let points = |value: fn(&Sample) -> f64| {
+    simulation
+      .samples
+      .iter()
+      .map(|sample| format!("{},{}", x(sample.time), y(value(sample))))
+      .collect::<Vec<_>>()
+      .join(" ")
+  };
+  svg![
+    attrs![
+        At::Width => px(OUTER.0),
+        At::Height => px(OUTER.1),
+        At::ViewBox => format!("0 0 {} {}", OUTER.0, OUTER.1),
+    ],
+    view_axis(elapsed_time, false),
+    simulation
+      .steps
+      .iter()
+      .filter(|run| run.exit != ExitReason::Time)
+      .map(|run| g![
+        line_![attrs![
+          At::X1 => x(run.end),
+          At::Y1 => INNER.1,
+          At::X2 => x(run.end),
+          At::Y2 => INNER.3,
+          At::Stroke => "gray",
+          At::StrokeDasharray => "2 2",
+        ]],
+        text![
+          attrs![
+            At::X => x(run.end) + 2.,
+            At::Y => INNER.1 + 10.,
+            At::FontSize => "10px",
+            At::Fill => "gray",
+          ],
+          run.exit.to_string(),
+        ],
+      ]),
+    polyline![attrs![
+      At::Points => points(|sample| sample.pressure),
+      At::Fill => "none",
+      At::Stroke => "darkgreen",
+      At::StrokeWidth => "1.5px",
+    ]],
+    polyline![attrs![
+      At::Points => points(|sample| sample.flow),
+      At::Fill => "none",
+      At::Stroke => "darkblue",
+      At::StrokeWidth => "1.5px",
+    ]],
   ]
 }
 
-fn view_axis(elapsed_time: f64) -> Node<Msg> {
-  let x_axis = Axis::new(
-    (0., elapsed_time),
-    (0., INNER.2 - INNER.0),
-    Direction::Horizontal,
-    10.0,
-  );
-  let y_axis = Axis::new((0., 12.), (0., INNER.1 - INNER.3), Direction::Vertical, 1.0);
+// Each limiter as a band over its range, topped by a line at the cap, in the
+// color of the series it caps.
+fn view_limits(limits: &[Limit], elapsed_time: f64) -> Node<Msg> {
+  let x = scale((0., elapsed_time), (INNER.0, INNER.2));
+  let y = scale((0., 12.), (INNER.3, INNER.1));
+  g![limits.iter().map(|limit| {
+    let color = match limit.series {
+      Series::Pressure => "darkgreen",
+      Series::Flow => "darkblue",
+      Series::Temperature => "darkred",
+    };
+    let (low, high) = limit.band();
+    g![
+      rect![attrs![
+        At::X => x(limit.start),
+        At::Y => y(high),
+        At::Width => x(limit.end) - x(limit.start),
+        At::Height => y(low) - y(high),
+        At::Fill => color,
+        At::Opacity => 0.15,
+      ]],
+      line_![attrs![
+        At::X1 => x(limit.start),
+        At::Y1 => y(high),
+        At::X2 => x(limit.end),
+        At::Y2 => y(high),
+        At::Stroke => color,
+        At::StrokeWidth => "1px",
+        At::StrokeDasharray => "6 2",
+        At::Opacity => 0.6,
+      ]],
+    ]
+  })]
+}
+
+fn view_highlight(timeline: &Timeline, highlight: &[usize], elapsed_time: f64) -> Node<Msg> {
+  let x = scale((0., elapsed_time), (INNER.0, INNER.2));
+  g![highlight
+    .iter()
+    .filter_map(|index| timeline.spans.get(*index))
+    .map(|(start, end)| rect![attrs![
+      At::X => x(*start),
+      At::Y => INNER.1,
+      At::Width => x(*end) - x(*start),
+      At::Height => INNER.3 - INNER.1,
+      At::Fill => "gold",
+      At::Opacity => 0.3,
+    ]])]
+}
+
+// With `grid` the ticks of both axes run across the chart.
+fn view_axis(elapsed_time: f64, grid: bool) -> Node<Msg> {
+  let (width, height) = (INNER.2 - INNER.0, INNER.1 - INNER.3);
+  let x_axis =
+    Axis::new((0., elapsed_time), (0., width), Direction::Horizontal, 10.0).title("seconds");
+  let y_axis = Axis::new((0., 12.), (0., height), Direction::Vertical, 1.0)
+    .format(|v| format!("{:.0}", v))
+    .title("bar / ml/s");
+  let (x_axis, y_axis) = if grid {
+    (x_axis.grid(height), y_axis.grid(width))
+  } else {
+    (x_axis, y_axis)
+  };
   g![
     g![
       attrs![At::Transform => format!("translate({},{})", INNER.0, INNER.3)],
@@ -48,44 +277,76 @@ fn view_axis(elapsed_time: f64) -> Node<Msg> {
   ]
 }
 
-fn view_graph_temperature(list: &PositionList, elapsed_time: f64) -> Node<Msg> {
+// Water-sensor steps target the mix at the group, not the basket, so they
+// are drawn lighter and dotted.
+fn view_graph_temperature(list: &[Segment], elapsed_time: f64) -> Node<Msg> {
+  let x = scale((0., elapsed_time), (INNER.0, INNER.2));
+  let y = scale((20., 100.), (INNER.3, INNER.1));
+  g![list.iter().map(|segment| {
+    let ((x1, y1), (x2, y2)) = (segment.start, segment.end);
+    let (color, dash) = match segment.sensor {
+      SensorType::Coffee => ("darkred", "none"),
+      SensorType::Water => ("orangered", "1 3"),
+    };
+    line_![attrs![
+      At::X1 => x(x1),
+      At::Y1 => y(y1),
+      At::X2 => x(x2),
+      At::Y2 => y(y2),
+      At::Stroke => color,
+      At::StrokeWidth => "1.5px",
+      At::StrokeLinecap => "round",
+      At::StrokeDasharray => dash,
+    ]]
+  })]
+}
+
+fn view_basket_temperature(points: &[(f64, f64)], elapsed_time: f64) -> Node<Msg> {
   let x = scale((0., elapsed_time), (INNER.0, INNER.2));
   let y = scale((20., 100.), (INNER.3, INNER.1));
-  g![list.iter().map(|(x1, y1, x2, y2)| line_![attrs![
-    At::X1 => x(*x1),
-    At::Y1 => y(*y1),
-    At::X2 => x(*x2),
-    At::Y2 => y(*y2),
+  polyline![attrs![
+    At::Points => points
+      .iter()
+      .map(|(t, v)| format!("{},{}", x(*t), y(*v)))
+      .collect::<Vec<_>>()
+      .join(" "),
+    At::Fill => "none",
     At::Stroke => "darkred",
-    At::StrokeWidth => "1.5px",
-    At::StrokeLinecap => "round",
-  ]])]
+    At::StrokeWidth => "1px",
+    At::Opacity => 0.5,
+  ]]
 }
 
-fn view_graph_pressure(list: &PositionList, elapsed_time: f64) -> Node<Msg> {
+fn view_graph_pressure(list: &[Segment], elapsed_time: f64) -> Node<Msg> {
   let x = scale((0., elapsed_time), (INNER.0, INNER.2));
   let y = scale((0., 12.), (INNER.3, INNER.1));
-  g![list.iter().map(|(x1, y1, x2, y2)| line_![attrs![
-    At::X1 => x(*x1),
-    At::Y1 => y(*y1),
-    At::X2 => x(*x2),
-    At::Y2 => y(*y2),
-    At::Stroke => "darkgreen",
-    At::StrokeWidth => "1.5px",
-    At::StrokeLinecap => "round",
-  ]])]
+  g![list.iter().map(|segment| {
+    let ((x1, y1), (x2, y2)) = (segment.start, segment.end);
+    line_![attrs![
+      At::X1 => x(x1),
+      At::Y1 => y(y1),
+      At::X2 => x(x2),
+      At::Y2 => y(y2),
+      At::Stroke => "darkgreen",
+      At::StrokeWidth => "1.5px",
+      At::StrokeLinecap => "round",
+    ]]
+  })]
 }
 
-fn view_graph_flow(list: &PositionList, elapsed_time: f64) -> Node<Msg> {
+fn view_graph_flow(list: &[Segment], elapsed_time: f64) -> Node<Msg> {
   let x = scale((0., elapsed_time), (INNER.0, INNER.2));
   let y = scale((0., 12.), (INNER.3, INNER.1));
-  g![list.iter().map(|(x1, y1, x2, y2)| line_![attrs![
-    At::X1 => x(*x1),
-    At::Y1 => y(*y1),
-    At::X2 => x(*x2),
-    At::Y2 => y(*y2),
-    At::Stroke => "darkblue",
-    At::StrokeWidth => "1.5px",
-    At::StrokeLinecap => "round",
-  ]])]
+  g![list.iter().map(|segment| {
+    let ((x1, y1), (x2, y2)) = (segment.start, segment.end);
+    line_![attrs![
+      At::X1 => x(x1),
+      At::Y1 => y(y1),
+      At::X2 => x(x2),
+      At::Y2 => y(y2),
+      At::Stroke => "darkblue",
+      At::StrokeWidth => "1.5px",
+      At::StrokeLinecap => "round",
+    ]]
+  })]
 }